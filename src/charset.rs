@@ -0,0 +1,210 @@
+use std::str::FromStr;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng
+};
+
+use crate::{config::ConfigError, render::display_width};
+
+// Named sets of characters that can be used in a charset spec.
+const PRESETS: [(&str, &[(u32, u32)]); 8] = [
+    // The half-width katakana block, as used in the film.
+    ("katakana", &[(0xFF66, 0xFF9D)]),
+    ("binary", &[(0x30, 0x31)]),
+    ("hex", &[(0x30, 0x39), (0x41, 0x46)]),
+    ("ascii", &[(0x21, 0x7E)]),
+    // U+03A2 is unassigned, so the capitals are split around it.
+    ("greek", &[(0x0391, 0x03A1), (0x03A3, 0x03A9), (0x03B1, 0x03C9)]),
+    // Leaves out U+2800, which is blank.
+    ("braille", &[(0x2801, 0x28FF)]),
+    ("digits", &[(0x30, 0x39)]),
+    ("emoji", &[(0x1F600, 0x1F64F)])
+];
+
+fn chars_in(ranges: &[(u32, u32)]) -> Vec<char> {
+    ranges.iter()
+        .flat_map(|&(start, end)| (start..=end).filter_map(char::from_u32))
+        .collect()
+}
+
+fn parse_code_point(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix("U+").or_else(|| s.strip_prefix("u+"))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// Parses U+XXXX or U+XXXX-U+YYYY.
+fn parse_range(s: &str) -> Result<Vec<char>, ()> {
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (s, s)
+    };
+    let start = parse_code_point(start).ok_or(())?;
+    let end = parse_code_point(end).ok_or(())?;
+    if start > end {
+        return Err(());
+    }
+
+    Ok(chars_in(&[(start, end)]))
+}
+
+// Parses one comma-separated part of a charset spec, without its weight: a
+// preset name, a range of code points, or the characters themselves.
+// Characters that take up no space on screen are left out.
+fn parse_part(s: &str) -> Result<Vec<char>, ()> {
+    let mut chars = parse_part_chars(s)?;
+    chars.retain(|&c| display_width(c) > 0);
+
+    Ok(chars)
+}
+
+fn parse_part_chars(s: &str) -> Result<Vec<char>, ()> {
+    if let Some((_, ranges)) = PRESETS.iter().find(|(name, _)| *name == s) {
+        return Ok(chars_in(ranges));
+    }
+    if s.starts_with("U+") || s.starts_with("u+") {
+        return parse_range(s);
+    }
+
+    Ok(s.chars().collect())
+}
+
+fn widest(sets: &[Vec<char>]) -> u16 {
+    sets.iter().flatten().map(|&c| display_width(c)).max().unwrap_or(1)
+}
+
+/// The characters the rain is made of. Made up of one or more sets, each
+/// picked with a chance proportional to its weight, and then a character is
+/// picked evenly from within the set.
+#[derive(Debug, Clone)]
+pub struct Charset {
+    sets: Vec<Vec<char>>,
+    weights: WeightedIndex<u32>,
+    // Width in columns of the widest character, worked out once since large
+    // ranges take a while to go through.
+    width: u16
+}
+
+impl Charset {
+    /// Returns an error if chars is empty or has any characters that take up
+    /// no space.
+    pub fn from_chars(chars: Vec<char>) -> Result<Charset, ConfigError> {
+        if chars.is_empty() {
+            return Err(ConfigError("a charset needs at least one character".to_string()));
+        }
+        if !chars.iter().all(|&c| display_width(c) > 0) {
+            return Err(ConfigError("charset characters must be visible".to_string()));
+        }
+
+        let sets = vec![chars];
+        Ok(Charset {
+            width: widest(&sets),
+            sets,
+            weights: WeightedIndex::new([1]).unwrap()
+        })
+    }
+
+    /// Width in columns of the widest character: 2 if there are any East
+    /// Asian wide characters, or 1.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> char {
+        let set = &self.sets[self.weights.sample(rng)];
+        set[rng.gen_range(0..set.len())]
+    }
+}
+
+/// Parses a comma-separated list of parts, each optionally followed by
+/// `:<weight>` (1 by default). For example "katakana:3,digits" draws katakana
+/// three times as often as digits.
+impl FromStr for Charset {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Charset, ()> {
+        let mut sets: Vec<Vec<char>> = vec![];
+        let mut weights: Vec<u32> = vec![];
+        // WeightedIndex adds the weights up in a u32.
+        let mut total: u32 = 0;
+
+        for part in s.split(',') {
+            // A colon not followed by a number is just another character.
+            let (part, weight) = match part.rsplit_once(':') {
+                Some((chars, weight)) if !chars.is_empty() => match weight.parse::<u32>() {
+                    Ok(weight) => (chars, weight),
+                    Err(_) => (part, 1)
+                },
+                _ => (part, 1)
+            };
+
+            let chars = parse_part(part)?;
+            if chars.is_empty() || weight == 0 {
+                return Err(());
+            }
+            total = total.checked_add(weight).ok_or(())?;
+            sets.push(chars);
+            weights.push(weight);
+        }
+
+        Ok(Charset {
+            width: widest(&sets),
+            sets,
+            weights: WeightedIndex::new(weights).map_err(|_| ())?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn set_chars(charset: &Charset) -> Vec<char> {
+        charset.sets.concat()
+    }
+
+    #[test]
+    fn katakana_is_the_half_width_block() {
+        let chars = set_chars(&"katakana".parse().unwrap());
+        assert_eq!(chars.len(), 0xFF9D - 0xFF66 + 1);
+        assert_eq!(chars[0], '\u{FF66}');
+        assert_eq!(*chars.last().unwrap(), '\u{FF9D}');
+    }
+
+    #[test]
+    fn ranges_and_literals_parse() {
+        assert_eq!(set_chars(&"U+30A1-U+30A3".parse().unwrap()), vec!['ァ', 'ア', 'ィ']);
+        assert_eq!(set_chars(&"u+41".parse().unwrap()), vec!['A']);
+        assert_eq!(set_chars(&"01".parse().unwrap()), vec!['0', '1']);
+        assert_eq!(set_chars(&"a:b".parse().unwrap()), vec!['a', ':', 'b']);
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        for spec in &["", "digits,", "U+30FF-U+30A0", "U+zz", "binary:0", "U+0300-U+036F", "a:4294967295,b:1"] {
+            assert!(spec.parse::<Charset>().is_err(), "{:?} parsed", spec);
+        }
+    }
+
+    #[test]
+    fn empty_or_invisible_chars_are_rejected() {
+        assert_eq!(set_chars(&Charset::from_chars(vec!['a', 'b']).unwrap()), vec!['a', 'b']);
+        assert!(Charset::from_chars(vec![]).is_err());
+        assert!(Charset::from_chars(vec!['a', '\u{0301}']).is_err());
+    }
+
+    #[test]
+    fn width_counts_wide_characters() {
+        assert_eq!("katakana,ascii".parse::<Charset>().unwrap().width(), 1);
+        assert_eq!("ascii,U+3091".parse::<Charset>().unwrap().width(), 2);
+        assert_eq!("emoji".parse::<Charset>().unwrap().width(), 2);
+    }
+
+    #[test]
+    fn weights_skew_sampling() {
+        let charset: Charset = "a:9,b".parse().unwrap();
+        let mut rng = thread_rng();
+        let a_count = (0..10_000).filter(|_| charset.sample(&mut rng) == 'a').count();
+        assert!(a_count > 8_500 && a_count < 9_500, "{} of 10000 were 'a'", a_count);
+    }
+}
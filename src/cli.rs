@@ -0,0 +1,834 @@
+use std::{
+    collections::HashMap,
+    env,
+    error,
+    ffi::{OsStr, OsString},
+    fmt,
+    fs,
+    io::ErrorKind,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration
+};
+use serde::Deserialize;
+use termion::event::Key;
+use matrix::{
+    charset::Charset,
+    config::{Layer, MAX_LAYERS},
+    gradient::{Easing, Gradient, Space},
+    theme::{self, Body, Theme},
+    Color,
+    ColorMode,
+    Config
+};
+
+use crate::{
+    intro::Intro,
+    keys::{self, Action, Bindings}
+};
+
+// Input and signals are only checked once a frame, so frames can't be much
+// further apart than this or quitting would take too long.
+const MIN_FPS: f64 = 1.0;
+
+pub const USAGE: &str = "\
+Usage: matrix [OPTIONS]
+
+Options:
+  --density <N>     Render 1 trail per N terminal squares [env: TRAIL_DENSITY] [default: 30]
+  --charset <SPEC>  Characters to sample the rain from: comma-separated presets (katakana,
+                    binary, hex, ascii, greek, braille, digits, emoji), U+XXXX-U+YYYY
+                    ranges or literal characters, each with an optional :<weight>
+                    [env: RAIN_CHARSET]
+  --fps <N>         Frames drawn per second, at least 1 [default: 30]
+  --color <COLOR>   Trail color: a name (green, red, blue, yellow, cyan, magenta, white),
+                    #rrggbb, or r,g,b with components in 0-255 [default: green]
+  --theme <NAME>    Color theme: classic, amber, tron, red-alert, rainbow, grayscale,
+                    or one defined in the config file [default: classic]
+  --head-color <C>  Color of the leading glyph of each trail, or none
+  --color-mode <M>  Colors to output: auto, truecolor, 256, 16 or mono [default: auto]
+  --max-len <N>     Maximum trail length, from 3 to 1000 [default: 12]
+  --layers <N>      Number of depth layers, up to 16; far layers are slower, shorter
+                    and dimmer [default: 1]
+  --gap <N>         Minimum empty cells between trails in the same column [default: 2]
+  --max-speed <N>   Maximum trail speed in cells per second, from 1 to 1000
+                    [default: 12]
+  --mutation <R>    Average changes per glyph per second [default: 0.3]
+  --seed <N>        Seed for the random number generator, so that runs on the same
+                    terminal size play out the same way [default: random]
+  --message <TEXT>  Text for passing trails to reveal in the middle of the screen, then
+                    hold and dissolve again, over and over
+  --message-hold <SECS>
+                    Seconds the message stays up, and the rain runs without it
+                    between reveals [default: 5]
+  --intro <PATH>    Script of lines to type out before the rain starts: one line of
+                    text per line, with @<seconds> on a line of its own to set how
+                    long the line before it stays up
+  --screensaver     Exit at once on any key or mouse event, for use as a screensaver or
+                    tmux lock-command
+  --duration <SECS> Exit after this many seconds of rain [default: forever]
+  --config <PATH>   Config file to load [default: ~/.config/matrix/config.toml]
+  --profile <NAME>  Profile from the config file to apply on top of [default]
+  -h, --help        Print this help
+  -V, --version     Print the version
+";
+
+// Error for invalid command-line flags, environment variables or config file.
+#[derive(Debug)]
+pub struct SettingsError(String);
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for SettingsError {}
+
+// Raw command-line arguments. Anything not given is None, so Settings::create
+// can fall back to the environment and then to the defaults.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub density: Option<u32>,
+    pub charset: Option<Charset>,
+    pub fps: Option<f64>,
+    pub color: Option<Color>,
+    pub theme: Option<String>,
+    pub head_color: Option<OptionalColor>,
+    pub color_mode: Option<ColorMode>,
+    pub max_len: Option<usize>,
+    pub gap: Option<u16>,
+    pub layers: Option<usize>,
+    pub max_speed: Option<f32>,
+    pub mutation_rate: Option<f32>,
+    pub seed: Option<u64>,
+    pub message: Option<String>,
+    pub message_hold: Option<f32>,
+    pub intro: Option<PathBuf>,
+    pub duration: Option<f64>,
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub screensaver: bool,
+    pub help: bool,
+    pub version: bool
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, SettingsError> {
+    T::from_str(value)
+        .map_err(|_| SettingsError(format!("invalid value '{}' for {}", value, flag)))
+}
+
+// The value of a flag as a string. Only paths may be anything other than
+// UTF-8.
+fn string_arg(flag: &str, value: OsString) -> Result<String, SettingsError> {
+    value.into_string()
+        .map_err(|v| SettingsError(format!("invalid value '{}' for {}", v.to_string_lossy(), flag)))
+}
+
+fn parse_arg<T: FromStr>(flag: &str, value: OsString) -> Result<T, SettingsError> {
+    parse_value(flag, &string_arg(flag, value)?)
+}
+
+impl Args {
+    // Parses flags of the form `--flag value` or `--flag=value`.
+    pub fn parse<I: Iterator<Item = OsString>>(mut args: I) -> Result<Args, SettingsError> {
+        let mut parsed = Args::default();
+
+        while let Some(arg) = args.next() {
+            let bytes = arg.as_bytes();
+            let (flag, inline_value) = match bytes.iter().position(|&b| b == b'=') {
+                Some(i) if bytes.starts_with(b"--") => (OsStr::from_bytes(&bytes[..i]), Some(OsStr::from_bytes(&bytes[i + 1..]).to_os_string())),
+                _ => (arg.as_os_str(), None)
+            };
+            let flag = flag.to_string_lossy().into_owned();
+
+            // Switches take no value, and the others take the next argument
+            // unless it was given inline. A value is only read once the flag
+            // is known, so an unknown flag is reported as such.
+            let is_switch = matches!(flag.as_str(), "-h" | "--help" | "-V" | "--version" | "--screensaver");
+            if is_switch && inline_value.is_some() {
+                return Err(SettingsError(format!("option '{}' takes no value", flag)));
+            }
+            let mut value = || match inline_value.clone().or_else(|| args.next()) {
+                Some(v) => Ok(v),
+                None => Err(SettingsError(format!("missing value for {}", flag)))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                "--screensaver" => parsed.screensaver = true,
+                "--density" => parsed.density = Some(parse_arg(&flag, value()?)?),
+                "--charset" => parsed.charset = Some(parse_arg(&flag, value()?)?),
+                "--fps" => parsed.fps = Some(parse_arg(&flag, value()?)?),
+                "--color" => parsed.color = Some(parse_arg(&flag, value()?)?),
+                "--theme" => parsed.theme = Some(string_arg(&flag, value()?)?),
+                "--head-color" => parsed.head_color = Some(parse_arg(&flag, value()?)?),
+                "--color-mode" => parsed.color_mode = Some(parse_arg(&flag, value()?)?),
+                "--max-len" => parsed.max_len = Some(parse_arg(&flag, value()?)?),
+                "--gap" => parsed.gap = Some(parse_arg(&flag, value()?)?),
+                "--layers" => parsed.layers = Some(parse_arg(&flag, value()?)?),
+                "--max-speed" => parsed.max_speed = Some(parse_arg(&flag, value()?)?),
+                "--mutation" => parsed.mutation_rate = Some(parse_arg(&flag, value()?)?),
+                "--seed" => parsed.seed = Some(parse_arg(&flag, value()?)?),
+                "--message" => parsed.message = Some(string_arg(&flag, value()?)?),
+                "--message-hold" => parsed.message_hold = Some(parse_arg(&flag, value()?)?),
+                "--intro" => parsed.intro = Some(PathBuf::from(value()?)),
+                "--duration" => parsed.duration = Some(parse_arg(&flag, value()?)?),
+                "--config" => parsed.config = Some(PathBuf::from(value()?)),
+                "--profile" => parsed.profile = Some(string_arg(&flag, value()?)?),
+                _ => return Err(SettingsError(format!("unknown option '{}'", flag)))
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+// One set of settings in the config file: either the [default] table or a
+// [profiles.<name>] table. Colors are strings in the same format as --color.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileSettings {
+    density: Option<u32>,
+    charset: Option<String>,
+    fps: Option<f64>,
+    trail_color: Option<String>,
+    fade_color: Option<String>,
+    theme: Option<String>,
+    background: Option<String>,
+    gradient: Option<Vec<String>>,
+    gradient_space: Option<String>,
+    easing: Option<String>,
+    head_color: Option<String>,
+    head_bold: Option<bool>,
+    head_glow: Option<bool>,
+    color_mode: Option<String>,
+    max_len: Option<usize>,
+    gap: Option<u16>,
+    max_speed: Option<f32>,
+    mutation_rate: Option<f32>,
+    layers: Option<FileLayers>,
+    message: Option<String>,
+    message_hold: Option<f32>,
+    // The intro script itself, in the same format as an --intro file.
+    intro: Option<String>,
+    screensaver: Option<bool>,
+    duration: Option<f64>,
+    // Maps action names to lists of key names.
+    keys: Option<HashMap<String, Vec<String>>>
+}
+
+impl FileSettings {
+    // Fills in anything unset in self from fallback.
+    fn or(self, fallback: FileSettings) -> FileSettings {
+        FileSettings {
+            density: self.density.or(fallback.density),
+            charset: self.charset.or(fallback.charset),
+            fps: self.fps.or(fallback.fps),
+            trail_color: self.trail_color.or(fallback.trail_color),
+            fade_color: self.fade_color.or(fallback.fade_color),
+            theme: self.theme.or(fallback.theme),
+            background: self.background.or(fallback.background),
+            gradient: self.gradient.or(fallback.gradient),
+            gradient_space: self.gradient_space.or(fallback.gradient_space),
+            easing: self.easing.or(fallback.easing),
+            head_color: self.head_color.or(fallback.head_color),
+            head_bold: self.head_bold.or(fallback.head_bold),
+            head_glow: self.head_glow.or(fallback.head_glow),
+            color_mode: self.color_mode.or(fallback.color_mode),
+            max_len: self.max_len.or(fallback.max_len),
+            gap: self.gap.or(fallback.gap),
+            max_speed: self.max_speed.or(fallback.max_speed),
+            mutation_rate: self.mutation_rate.or(fallback.mutation_rate),
+            layers: self.layers.or(fallback.layers),
+            message: self.message.or(fallback.message),
+            message_hold: self.message_hold.or(fallback.message_hold),
+            intro: self.intro.or(fallback.intro),
+            screensaver: self.screensaver.or(fallback.screensaver),
+            duration: self.duration.or(fallback.duration),
+            keys: match (self.keys, fallback.keys) {
+                (Some(mut keys), Some(fallback_keys)) => {
+                    for (action, action_keys) in fallback_keys {
+                        keys.entry(action).or_insert(action_keys);
+                    }
+                    Some(keys)
+                },
+                (keys, fallback_keys) => keys.or(fallback_keys)
+            }
+        }
+    }
+}
+
+// Either a number of evenly spread out layers, or a list of [[layers]] tables
+// from farthest to nearest.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FileLayers {
+    Count(usize),
+    List(Vec<FileLayer>)
+}
+
+// Each setting is a fraction of the one for the whole rain, and 1 if left out.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileLayer {
+    speed: Option<f32>,
+    length: Option<f32>,
+    brightness: Option<f32>,
+    // Replaces the gradient of the theme for this layer.
+    gradient: Option<Vec<String>>
+}
+
+// A user-defined theme, from a [themes.<name>] table. The body is either a
+// gradient or a rainbow; anything else left out is off.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileTheme {
+    head: Option<String>,
+    gradient: Option<Vec<String>>,
+    #[serde(default)]
+    rainbow: bool,
+    background: Option<String>
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    default: FileSettings,
+    profiles: HashMap<String, FileSettings>,
+    themes: HashMap<String, FileTheme>
+}
+
+fn parse_colors(name: &str, colors: &[String]) -> Result<Vec<Color>, SettingsError> {
+    let colors: Vec<Color> = colors.iter()
+        .map(|s| parse_value(name, s))
+        .collect::<Result<_, _>>()?;
+
+    if colors.is_empty() {
+        return Err(SettingsError(format!("{} must have at least one color", name)));
+    }
+
+    Ok(colors)
+}
+
+impl FileTheme {
+    fn into_theme(self, name: String) -> Result<Theme, SettingsError> {
+        let body = match (self.rainbow, &self.gradient) {
+            (true, None) => Body::Rainbow,
+            (false, Some(stops)) => Body::Gradient(parse_colors("gradient", stops)?),
+            _ => return Err(SettingsError(format!("theme '{}' needs either a gradient or rainbow = true", name)))
+        };
+        let head: Option<OptionalColor> = match &self.head {
+            Some(s) => Some(parse_value("head", s)?),
+            None => None
+        };
+        let background: Option<OptionalColor> = match &self.background {
+            Some(s) => Some(parse_value("background", s)?),
+            None => None
+        };
+
+        Ok(Theme::new(&name, head.and_then(|c| c.0), body, background.and_then(|c| c.0)))
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("matrix").join("config.toml"))
+}
+
+// Loads the config file, resolving the selected profile, and any themes it
+// defines. A missing file is only an error if it was explicitly asked for.
+fn load_file(args: &Args) -> Result<(FileSettings, Vec<Theme>), SettingsError> {
+    let path = match args.config.clone().or_else(default_config_path) {
+        Some(path) => path,
+        None => return Ok((FileSettings::default(), vec![]))
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound && args.config.is_none() => String::new(),
+        Err(e) => return Err(SettingsError(format!("cannot read {}: {}", path.display(), e)))
+    };
+
+    let mut file: ConfigFile = toml::from_str(&contents)
+        .map_err(|e| SettingsError(format!("invalid config file {}: {}", path.display(), e)))?;
+
+    let mut themes: Vec<Theme> = vec![];
+    for (name, theme) in file.themes {
+        themes.push(theme.into_theme(name)?);
+    }
+    themes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let settings = match &args.profile {
+        Some(name) => match file.profiles.remove(name) {
+            Some(profile) => profile.or(file.default),
+            None => return Err(SettingsError(format!("no profile '{}' in {}", name, path.display())))
+        },
+        None => file.default
+    };
+
+    Ok((settings, themes))
+}
+
+// A color, or "none".
+#[derive(Debug, Clone, Copy)]
+pub struct OptionalColor(Option<Color>);
+
+impl FromStr for OptionalColor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<OptionalColor, ()> {
+        match s {
+            "none" => Ok(OptionalColor(None)),
+            _ => Ok(OptionalColor(Some(Color::from_str(s)?)))
+        }
+    }
+}
+
+// What the program runs with: the config of the rain, and how the program
+// around it behaves.
+pub struct Settings {
+    pub config: Config,
+    // Themes that can be switched between, and the one to start with.
+    pub themes: Vec<Theme>,
+    pub theme_index: usize,
+    // Keyboard controls.
+    pub keys: Bindings,
+    // Lines typed out before the rain starts.
+    pub intro: Option<Intro>,
+    // Whether any key or mouse event exits, rather than only the quit keys.
+    pub screensaver: bool,
+    // How long the rain runs before exiting, or None to run until quit.
+    pub duration: Option<Duration>
+}
+
+impl Settings {
+    // Builds the settings from, in order of precedence: flags, environment
+    // variables, the selected profile, the [default] table of the config file,
+    // and finally the built-in defaults.
+    pub fn create(args: &Args) -> Result<Settings, SettingsError> {
+        Settings::create_with(args, |name| env::var(name).ok())
+    }
+
+    // Like create, but looks environment variables up with var rather than
+    // in the environment of the process.
+    pub fn create_with<F: Fn(&str) -> Option<String>>(args: &Args, var: F) -> Result<Settings, SettingsError> {
+        let (file, file_themes) = load_file(args)?;
+        let mut config = Config::default();
+
+        let trail_density_env: Option<u32> = match var("TRAIL_DENSITY") {
+            Some(s) => Some(parse_value("TRAIL_DENSITY", &s)?),
+            None => None
+        };
+
+        config.trail_density = args.density
+            .or(trail_density_env)
+            .or(file.density)
+            .unwrap_or(config.trail_density);
+
+        let rain_charset_env: Option<Charset> = match var("RAIN_CHARSET") {
+            Some(s) => Some(parse_value("RAIN_CHARSET", &s)?),
+            None => None
+        };
+        let file_charset: Option<Charset> = match &file.charset {
+            Some(s) => Some(parse_value("charset", s)?),
+            None => None
+        };
+
+        if let Some(charset) = args.charset.clone().or(rain_charset_env).or(file_charset) {
+            config.rain_charset = charset;
+        }
+
+        // A theme picked with --theme wins over colors from the file, as flags
+        // win over the file everywhere else. They are still checked, though.
+        let use_file_colors = args.theme.is_none();
+
+        let file_trail_color: Option<Color> = match &file.trail_color {
+            Some(s) => Some(parse_value("trail-color", s)?),
+            None => None
+        };
+        let file_fade_color: Option<Color> = match &file.fade_color {
+            Some(s) => Some(parse_value("fade-color", s)?),
+            None => None
+        };
+        let file_gradient: Option<Vec<Color>> = match &file.gradient {
+            Some(stops) => Some(parse_colors("gradient", stops)?),
+            None => None
+        };
+
+        // Explicit colors override the body of the theme. --color sets the
+        // whole trail, so the fade is derived from it rather than taken from
+        // the file. A gradient list in the file takes precedence over its
+        // trail and fade colors.
+        let stops: Option<Vec<Color>> = match (args.color, file_gradient) {
+            (Some(color), _) => Some(vec![color, color.dim()]),
+            _ if !use_file_colors => None,
+            (None, Some(stops)) => Some(stops),
+            (None, None) if file_trail_color.is_some() || file_fade_color.is_some() => {
+                let trail_color = file_trail_color.unwrap_or(Color::PURE_GREEN);
+                Some(vec![trail_color, file_fade_color.unwrap_or_else(|| trail_color.dim())])
+            },
+            (None, None) => None
+        };
+
+        // User themes replace built-in ones of the same name.
+        let mut themes = theme::builtin();
+        for user_theme in file_themes {
+            match themes.iter_mut().find(|t| t.name == user_theme.name) {
+                Some(t) => *t = user_theme,
+                None => themes.push(user_theme)
+            }
+        }
+
+        let theme_name = args.theme.clone()
+            .or_else(|| file.theme.clone())
+            .unwrap_or_else(|| theme::DEFAULT_THEME.to_string());
+        let theme_index = match themes.iter().position(|t| t.name == theme_name) {
+            Some(i) => i,
+            None => return Err(SettingsError(format!("unknown theme '{}'", theme_name)))
+        };
+
+        let file_background: Option<OptionalColor> = match &file.background {
+            Some(s) => Some(parse_value("background", s)?),
+            None => None
+        };
+
+        let space: Space = match &file.gradient_space {
+            Some(s) => parse_value("gradient-space", s)?,
+            None => Space::Rgb
+        };
+        let easing: Easing = match &file.easing {
+            Some(s) => parse_value("easing", s)?,
+            None => Easing::Linear
+        };
+
+        let file_head_color: Option<OptionalColor> = match &file.head_color {
+            Some(s) => Some(parse_value("head-color", s)?),
+            None => None
+        };
+        let head_color = args.head_color.or(file_head_color.filter(|_| use_file_colors));
+
+        let file_color_mode: Option<ColorMode> = match &file.color_mode {
+            Some(s) => Some(parse_value("color-mode", s)?),
+            None => None
+        };
+        config.color_mode = args.color_mode
+            .or(file_color_mode)
+            .unwrap_or_else(ColorMode::detect);
+
+        let mut key_overrides: HashMap<Action, Vec<Key>> = HashMap::new();
+        for (name, key_names) in file.keys.iter().flatten() {
+            let action: Action = Action::from_str(name)
+                .map_err(|_| SettingsError(format!("unknown action '{}' in keys", name)))?;
+            let action_keys = key_names.iter()
+                .map(|k| keys::parse_key(k)
+                    .ok_or_else(|| SettingsError(format!("invalid key '{}' for {}", k, name))))
+                .collect::<Result<_, _>>()?;
+            key_overrides.insert(action, action_keys);
+        }
+
+        // Ctrl-C doesn't raise SIGINT in raw mode, so without a quit key
+        // only kill could stop the rain.
+        let keys = Bindings::new(&key_overrides);
+        if !keys.is_bound(Action::Quit) {
+            return Err(SettingsError("keys leave nothing bound to quit".to_string()));
+        }
+
+        config.layers = match (args.layers, file.layers) {
+            // Checked here as well as by validate, so a huge count isn't
+            // allocated first.
+            (Some(count), _) | (None, Some(FileLayers::Count(count))) if count > MAX_LAYERS => {
+                return Err(SettingsError(format!("there must be between 1 and {} layers", MAX_LAYERS)));
+            },
+            (Some(count), _) | (None, Some(FileLayers::Count(count))) => Layer::spread(count),
+            (None, Some(FileLayers::List(file_layers))) => {
+                let mut layers = vec![];
+                for file_layer in file_layers {
+                    let gradient = match &file_layer.gradient {
+                        Some(stops) => Some(Gradient::new(parse_colors("layer gradient", stops)?, space, easing).map_err(|e| SettingsError(e.to_string()))?),
+                        None => None
+                    };
+                    let mut layer = Layer::new(
+                        file_layer.speed.unwrap_or(1.0),
+                        file_layer.length.unwrap_or(1.0),
+                        file_layer.brightness.unwrap_or(1.0)
+                    );
+                    layer.gradient = gradient;
+                    layers.push(layer);
+                }
+                layers
+            },
+            (None, None) => vec![Layer::default()]
+        };
+
+        let intro: Option<Intro> = match (&args.intro, &file.intro) {
+            (Some(path), _) => {
+                let script = fs::read_to_string(path)
+                    .map_err(|e| SettingsError(format!("cannot read {}: {}", path.display(), e)))?;
+                let intro = script.parse()
+                    .map_err(|_| SettingsError(format!("invalid intro script {}", path.display())))?;
+                Some(intro)
+            },
+            (None, Some(script)) => {
+                let intro = script.parse()
+                    .map_err(|_| SettingsError("invalid intro script in config file".to_string()))?;
+                Some(intro)
+            },
+            (None, None) => None
+        };
+
+        if let Some(fps) = args.fps.or(file.fps) {
+            if !(fps >= MIN_FPS && fps.is_finite()) {
+                return Err(SettingsError(format!("fps must be at least {}, got {}", MIN_FPS, fps)));
+            }
+            config.frame_time = Duration::from_secs_f64(1.0 / fps);
+        }
+
+        let duration = match args.duration.or(file.duration) {
+            Some(secs) => match Duration::try_from_secs_f64(secs) {
+                Ok(duration) if secs > 0.0 => Some(duration),
+                _ => return Err(SettingsError(format!("duration must be positive and not too long, got {}", secs)))
+            },
+            None => None
+        };
+
+        config.gradient = Gradient::new(vec![Color::PURE_GREEN, Color::DARK_GREEN], space, easing).map_err(|e| SettingsError(e.to_string()))?;
+        config.head.bold = file.head_bold.unwrap_or(config.head.bold);
+        config.head.glow = file.head_glow.unwrap_or(config.head.glow);
+        config.max_len = args.max_len.or(file.max_len).unwrap_or(config.max_len);
+        config.gap = args.gap.or(file.gap).unwrap_or(config.gap);
+        config.max_speed = args.max_speed.or(file.max_speed).unwrap_or(config.max_speed);
+        config.mutation_rate = args.mutation_rate.or(file.mutation_rate).unwrap_or(config.mutation_rate);
+        config.seed = args.seed;
+        config.message = args.message.clone().or(file.message);
+        config.message_hold = args.message_hold.or(file.message_hold).unwrap_or(config.message_hold);
+
+        config.apply_theme(&themes[theme_index]).map_err(|e| SettingsError(e.to_string()))?;
+        if let Some(stops) = stops {
+            config.gradient = config.gradient.with_stops(stops).map_err(|e| SettingsError(e.to_string()))?;
+            config.rainbow = false;
+        }
+        if let Some(OptionalColor(color)) = head_color {
+            config.head.color = color;
+        }
+        if let Some(OptionalColor(color)) = file_background.filter(|_| use_file_colors) {
+            config.background = color;
+        }
+        config.validate().map_err(|e| SettingsError(e.to_string()))?;
+
+        Ok(Settings {
+            config,
+            themes,
+            theme_index,
+            keys,
+            intro,
+            screensaver: args.screensaver || file.screensaver.unwrap_or(false),
+            duration
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use matrix::config::{MAX_TRAIL_LEN, MAX_TRAIL_SPEED};
+
+    fn parse(args: &[&str]) -> Result<Args, SettingsError> {
+        Args::parse(args.iter().map(OsString::from))
+    }
+
+    fn error(args: &[&str]) -> String {
+        parse(args).unwrap_err().to_string()
+    }
+
+    // Builds the settings from args with toml as the config file, written
+    // out under name in the temporary directory, and vars as the only
+    // environment variables.
+    fn create_in_env(name: &str, toml: &str, args: Args, vars: &[(&str, &str)]) -> Result<Settings, SettingsError> {
+        let vars: HashMap<&str, &str> = vars.iter().cloned().collect();
+        let path = env::temp_dir().join(format!("matrix-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, toml).unwrap();
+        let settings = Settings::create_with(&Args { config: Some(path.clone()), ..args }, |name| vars.get(name).map(|s| s.to_string()));
+        fs::remove_file(&path).unwrap();
+
+        settings
+    }
+
+    fn create(name: &str, toml: &str, args: Args) -> Result<Settings, SettingsError> {
+        create_in_env(name, toml, args, &[])
+    }
+
+    #[test]
+    fn flags_take_separate_or_inline_values() {
+        let args = parse(&["--density", "12", "--fps=60", "--theme=amber", "-h"]).unwrap();
+        assert_eq!(args.density, Some(12));
+        assert_eq!(args.fps, Some(60.0));
+        assert_eq!(args.theme, Some("amber".to_string()));
+        assert!(args.help);
+    }
+
+    #[test]
+    fn bad_flags_are_reported() {
+        assert_eq!(error(&["--density"]), "missing value for --density");
+        assert_eq!(error(&["--speed", "3"]), "unknown option '--speed'");
+        assert_eq!(error(&["--density", "lots"]), "invalid value 'lots' for --density");
+        assert_eq!(error(&["--fps=-"]), "invalid value '-' for --fps");
+        assert_eq!(error(&["--bogus"]), "unknown option '--bogus'");
+        assert_eq!(error(&["foo"]), "unknown option 'foo'");
+        assert_eq!(error(&["--bogus=1"]), "unknown option '--bogus'");
+        assert_eq!(error(&["--screensaver=false"]), "option '--screensaver' takes no value");
+        assert_eq!(error(&["--help=x"]), "option '--help' takes no value");
+    }
+
+    #[test]
+    fn only_paths_may_be_other_than_utf8() {
+        let arg = |bytes: &[u8]| OsStr::from_bytes(bytes).to_os_string();
+
+        let args = Args::parse(vec![arg(b"--config"), arg(b"/tmp/\xff"), arg(b"--intro=/tmp/\xfe")].into_iter()).unwrap();
+        assert_eq!(args.config.unwrap().as_os_str().as_bytes(), b"/tmp/\xff");
+        assert_eq!(args.intro.unwrap().as_os_str().as_bytes(), b"/tmp/\xfe");
+
+        let error = |args: Vec<OsString>| Args::parse(args.into_iter()).unwrap_err().to_string();
+        assert_eq!(error(vec![arg(b"--message"), arg(b"hi\xff")]), "invalid value 'hi\u{FFFD}' for --message");
+        assert_eq!(error(vec![arg(b"--density=\xff")]), "invalid value '\u{FFFD}' for --density");
+        assert_eq!(error(vec![arg(b"--\xff")]), "unknown option '--\u{FFFD}'");
+    }
+
+    const PROFILES: &str = r#"
+        [default]
+        density = 10
+        gap = 4
+        max-len = 20
+
+        [default.keys]
+        pause = ["p"]
+        quit = ["x"]
+
+        [profiles.lobby]
+        density = 20
+        max-len = 30
+
+        [profiles.lobby.keys]
+        pause = ["o"]
+    "#;
+
+    #[test]
+    fn settings_follow_precedence() {
+        let profile = || Args { profile: Some("lobby".to_string()), ..Args::default() };
+
+        let config = create("default", PROFILES, Args::default()).unwrap().config;
+        assert_eq!((config.trail_density, config.gap, config.max_len), (10, 4, 20));
+
+        // The profile is layered on top of [default].
+        let config = create("profile", PROFILES, profile()).unwrap().config;
+        assert_eq!((config.trail_density, config.gap, config.max_len), (20, 4, 30));
+
+        // The environment wins over the file, and flags win over both.
+        let vars = [("TRAIL_DENSITY", "50")];
+        let from_env = create_in_env("env", PROFILES, profile(), &vars);
+        let from_flag = create_in_env("flag", PROFILES, Args { density: Some(40), ..profile() }, &vars);
+        assert_eq!(from_env.unwrap().config.trail_density, 50);
+        assert_eq!(from_flag.unwrap().config.trail_density, 40);
+    }
+
+    #[test]
+    fn profile_keys_merge_with_default_keys() {
+        let settings = create("keys", PROFILES, Args { profile: Some("lobby".to_string()), ..Args::default() }).unwrap();
+        assert_eq!(settings.keys.action(Key::Char('o')), Some(Action::Pause));
+        assert_eq!(settings.keys.action(Key::Char('p')), None);
+        assert_eq!(settings.keys.action(Key::Char('x')), Some(Action::Quit));
+        assert_eq!(settings.keys.action(Key::Char('q')), None);
+        assert_eq!(settings.keys.action(Key::Char(' ')), None);
+    }
+
+    #[test]
+    fn quit_must_keep_a_key() {
+        assert!(create("quit-empty", "[default.keys]\nquit = []", Args::default()).is_err());
+        let moved = "[default.keys]\npause = [\"q\", \"esc\"]\nhelp = [\"ctrl-c\"]";
+        assert!(create("quit-moved", moved, Args::default()).is_err());
+        let partly_moved = "[default.keys]\npause = [\"q\", \"esc\"]";
+        let settings = create("quit-partly-moved", partly_moved, Args::default()).unwrap();
+        assert_eq!(settings.keys.action(Key::Ctrl('c')), Some(Action::Quit));
+    }
+
+    #[test]
+    fn theme_flag_wins_over_file_colors() {
+        let file = r##"
+            [default]
+            trail-color = "green"
+            fade-color = "0,51,0"
+            head-color = "#e0ffe0"
+            background = "#000800"
+        "##;
+        let amber = theme::builtin().into_iter().find(|t| t.name == "amber").unwrap();
+        let green = Color { r: 0, g: 255, b: 0 };
+
+        let config = create("theme-file", file, Args::default()).unwrap().config;
+        assert_eq!(config.gradient.at(0.0), green);
+        assert_eq!(config.head.color, Some(Color { r: 0xe0, g: 0xff, b: 0xe0 }));
+        assert_eq!(config.background, Some(Color { r: 0, g: 8, b: 0 }));
+
+        let config = create("theme-flag", file, Args { theme: Some("amber".to_string()), ..Args::default() }).unwrap().config;
+        match &amber.body {
+            Body::Gradient(stops) => assert_eq!(config.gradient.at(0.0), stops[0]),
+            _ => unreachable!()
+        }
+        assert_eq!(config.head.color, amber.head);
+        assert_eq!(config.background, amber.background);
+
+        // Color flags still win over the theme.
+        let args = Args { theme: Some("amber".to_string()), color: Some(green), ..Args::default() };
+        assert_eq!(create("theme-color-flag", file, args).unwrap().config.gradient.at(0.0), green);
+    }
+
+    #[test]
+    fn trail_limits_are_enforced() {
+        let config = |args| create("limits", "", args).map(|s| (s.config.max_len, s.config.max_speed));
+        assert_eq!(config(Args { max_len: Some(MAX_TRAIL_LEN), max_speed: Some(MAX_TRAIL_SPEED), ..Args::default() }).unwrap(), (MAX_TRAIL_LEN, MAX_TRAIL_SPEED));
+        assert!(config(Args { max_len: Some(1_000_000), ..Args::default() }).is_err());
+        assert!(config(Args { max_len: Some(2), ..Args::default() }).is_err());
+        assert!(config(Args { max_speed: Some(1e6), ..Args::default() }).is_err());
+        assert!(config(Args { max_speed: Some(f32::INFINITY), ..Args::default() }).is_err());
+    }
+
+    #[test]
+    fn layers_stay_within_trail_limits() {
+        let layer = |fields| create("layer-limits", &format!("[[default.layers]]\n{}", fields), Args::default()).map(|s| s.config.layers.len());
+        assert_eq!(layer("speed = 1.0\nlength = 0.5").unwrap(), 1);
+        for fields in &["length = 1e7", "length = 1e9", "speed = 1.5", "speed = 0.0", "length = -1.0", "speed = nan"] {
+            assert!(layer(fields).is_err(), "layer with {} accepted", fields);
+        }
+    }
+
+    #[test]
+    fn layer_count_is_limited() {
+        let layers = |count| create("layers", "", Args { layers: Some(count), ..Args::default() }).map(|s| s.config.layers.len());
+        assert_eq!(layers(MAX_LAYERS).unwrap(), MAX_LAYERS);
+        assert!(layers(MAX_LAYERS + 1).is_err());
+        assert!(layers(usize::MAX).is_err());
+        assert!(layers(0).is_err());
+    }
+
+    #[test]
+    fn tiny_fps_is_rejected() {
+        assert!(create("fps", "", Args { fps: Some(1.0), ..Args::default() }).is_ok());
+        for &fps in &[0.001, 1e-30, 0.0, f64::INFINITY] {
+            assert!(create("fps", "", Args { fps: Some(fps), ..Args::default() }).is_err(), "fps {} accepted", fps);
+        }
+    }
+
+    #[test]
+    fn durations_out_of_range_are_rejected() {
+        let duration = |secs| create("duration", "", Args { duration: Some(secs), ..Args::default() }).map(|s| s.duration);
+        assert_eq!(duration(90.0).unwrap(), Some(Duration::from_secs(90)));
+        for &secs in &[0.0, -1.0, 1e30, f64::INFINITY, f64::NAN] {
+            assert!(duration(secs).is_err(), "duration {} accepted", secs);
+        }
+    }
+}
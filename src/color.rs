@@ -0,0 +1,287 @@
+use std::{
+    env,
+    io::{Write, Error},
+    str::FromStr
+};
+use termion::color;
+
+/// A 24-bit RGB color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+impl Color {
+    pub const PURE_GREEN: Color = Color { r: 0, g: 255, b: 0 };
+    pub const DARK_GREEN: Color = Color { r: 0, g: 51, b: 0 };
+
+    /// Colors that can be given by name.
+    pub const NAMED: [(&'static str, Color); 7] = [
+        ("green", Color::PURE_GREEN),
+        ("cyan", Color { r: 0, g: 255, b: 255 }),
+        ("blue", Color { r: 0, g: 0, b: 255 }),
+        ("magenta", Color { r: 255, g: 0, b: 255 }),
+        ("red", Color { r: 255, g: 0, b: 0 }),
+        ("yellow", Color { r: 255, g: 255, b: 0 }),
+        ("white", Color { r: 255, g: 255, b: 255 })
+    ];
+
+    /// A dark shade of this color, for the faded end of a trail.
+    pub fn dim(self) -> Color {
+        Color { r: self.r / 5, g: self.g / 5, b: self.b / 5 }
+    }
+
+    /// This color with each channel multiplied by factor.
+    pub fn scale(self, factor: f32) -> Color {
+        let channel = |c: u8| (c as f32 * factor).round().clamp(0.0, 255.0) as u8;
+        Color { r: channel(self.r), g: channel(self.g), b: channel(self.b) }
+    }
+
+    /// A fully saturated, full brightness color of the given hue in degrees.
+    pub fn from_hue(hue: f32) -> Color {
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let x = 1.0 - (h % 2.0 - 1.0).abs();
+        let (r, g, b) = match h as u32 {
+            0 => (1.0, x, 0.0),
+            1 => (x, 1.0, 0.0),
+            2 => (0.0, 1.0, x),
+            3 => (0.0, x, 1.0),
+            4 => (x, 0.0, 1.0),
+            _ => (1.0, 0.0, x)
+        };
+        let channel = |v: f32| (v * 255.0).round() as u8;
+
+        Color { r: channel(r), g: channel(g), b: channel(b) }
+    }
+
+    /// Writes the escape sequence setting this as the foreground color, using
+    /// the closest color the terminal supports.
+    pub fn write_fg<W: Write>(self, out: &mut W, mode: ColorMode) -> Result<(), Error> {
+        match mode {
+            ColorMode::TrueColor => write!(out, "{}", color::Fg(color::Rgb(self.r, self.g, self.b))),
+            ColorMode::Ansi256 => write!(out, "{}", color::Fg(color::AnsiValue(self.nearest_ansi256()))),
+            ColorMode::Ansi16 => {
+                // SGR 30-37 are the normal colors and 90-97 the bright ones.
+                let i = self.nearest_ansi16(false);
+                let code = if i < 8 { 30 + i } else { 90 + i - 8 };
+                write!(out, "\x1b[{}m", code)
+            },
+            ColorMode::Mono => Ok(())
+        }
+    }
+
+    /// Like write_fg, for the background color.
+    pub fn write_bg<W: Write>(self, out: &mut W, mode: ColorMode) -> Result<(), Error> {
+        match mode {
+            ColorMode::TrueColor => write!(out, "{}", color::Bg(color::Rgb(self.r, self.g, self.b))),
+            ColorMode::Ansi256 => write!(out, "{}", color::Bg(color::AnsiValue(self.nearest_ansi256()))),
+            ColorMode::Ansi16 => {
+                // SGR 40-47 are the normal colors and 100-107 the bright ones.
+                let i = self.nearest_ansi16(true);
+                let code = if i < 8 { 40 + i } else { 100 + i - 8 };
+                write!(out, "\x1b[{}m", code)
+            },
+            ColorMode::Mono => Ok(())
+        }
+    }
+
+    // Index of the closest color in the 6x6x6 cube of the 256-color palette.
+    pub(crate) fn nearest_ansi256(self) -> u8 {
+        16 + 36 * cube_index(self.r) + 6 * cube_index(self.g) + cube_index(self.b)
+    }
+
+    // Index into ANSI16_PALETTE of the closest basic terminal color. Unless
+    // allow_black is set, black is never picked, so dark shades of text stay
+    // visible instead of vanishing into the background.
+    pub(crate) fn nearest_ansi16(self, allow_black: bool) -> u8 {
+        let distance = |c: &Color| {
+            let dr = self.r as i32 - c.r as i32;
+            let dg = self.g as i32 - c.g as i32;
+            let db = self.b as i32 - c.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+
+        ANSI16_PALETTE.iter()
+            .enumerate()
+            .skip(if allow_black { 0 } else { 1 })
+            .min_by_key(|(_, c)| distance(c))
+            .map(|(i, _)| i as u8)
+            .unwrap_or(7)
+    }
+}
+
+// Approximate RGB values of the 16 basic terminal colors (xterm defaults).
+const ANSI16_PALETTE: [Color; 16] = [
+    Color { r: 0, g: 0, b: 0 },
+    Color { r: 205, g: 0, b: 0 },
+    Color { r: 0, g: 205, b: 0 },
+    Color { r: 205, g: 205, b: 0 },
+    Color { r: 0, g: 0, b: 238 },
+    Color { r: 205, g: 0, b: 205 },
+    Color { r: 0, g: 205, b: 205 },
+    Color { r: 229, g: 229, b: 229 },
+    Color { r: 127, g: 127, b: 127 },
+    Color { r: 255, g: 0, b: 0 },
+    Color { r: 0, g: 255, b: 0 },
+    Color { r: 255, g: 255, b: 0 },
+    Color { r: 92, g: 92, b: 255 },
+    Color { r: 255, g: 0, b: 255 },
+    Color { r: 0, g: 255, b: 255 },
+    Color { r: 255, g: 255, b: 255 }
+];
+
+// Maps a 0-255 channel to the closest of the 256-color cube levels
+// (0, 95, 135, 175, 215, 255), returned as an index in 0-5.
+fn cube_index(c: u8) -> u8 {
+    match c {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (c - 35) / 40
+    }
+}
+
+impl FromStr for Color {
+    type Err = ();
+
+    /// Parses a color name, #rrggbb, or r,g,b with each component in 0-255.
+    fn from_str(s: &str) -> Result<Color, ()> {
+        if let Some((_, color)) = Color::NAMED.iter().find(|(name, _)| *name == s) {
+            return Ok(*color);
+        }
+
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(());
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ());
+            return Ok(Color { r: channel(0)?, g: channel(2)?, b: channel(4)? });
+        }
+
+        let components: Vec<u8> = s.split(',')
+            .map(|c| u8::from_str(c.trim()).map_err(|_| ()))
+            .collect::<Result<_, _>>()?;
+
+        match components[..] {
+            [r, g, b] => Ok(Color { r, g, b }),
+            _ => Err(())
+        }
+    }
+}
+
+/// How many colors the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ColorMode {
+    /// 24-bit RGB.
+    TrueColor,
+    /// The xterm 256-color palette.
+    Ansi256,
+    /// The 16 basic colors.
+    Ansi16,
+    /// No colors at all.
+    Mono
+}
+
+impl ColorMode {
+    /// Guesses the color support of the terminal from the environment.
+    pub fn detect() -> ColorMode {
+        if env::var_os("NO_COLOR").is_some() {
+            return ColorMode::Mono;
+        }
+
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorMode::TrueColor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            ColorMode::Mono
+        } else if term.contains("256color") {
+            ColorMode::Ansi256
+        } else {
+            ColorMode::Ansi16
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ColorMode, ()> {
+        match s {
+            "auto" => Ok(ColorMode::detect()),
+            "truecolor" | "24bit" => Ok(ColorMode::TrueColor),
+            "256" => Ok(ColorMode::Ansi256),
+            "16" => Ok(ColorMode::Ansi16),
+            "mono" => Ok(ColorMode::Mono),
+            _ => Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_snap_to_the_nearest_cube_level() {
+        let levels: Vec<u8> = [0, 47, 48, 114, 115, 154, 155, 194, 195, 234, 235, 255]
+            .iter()
+            .map(|&c| cube_index(c))
+            .collect();
+        assert_eq!(levels, vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5]);
+
+        assert_eq!(Color { r: 0, g: 0, b: 0 }.nearest_ansi256(), 16);
+        assert_eq!(Color { r: 255, g: 255, b: 255 }.nearest_ansi256(), 231);
+        assert_eq!(Color { r: 0, g: 255, b: 0 }.nearest_ansi256(), 46);
+        assert_eq!(Color { r: 100, g: 140, b: 20 }.nearest_ansi256(), 16 + 36 + 6 * 2);
+    }
+
+    #[test]
+    fn ansi16_skips_black_unless_allowed() {
+        let near_black = Color { r: 40, g: 40, b: 40 };
+        assert_eq!(near_black.nearest_ansi16(true), 0);
+        assert_eq!(near_black.nearest_ansi16(false), 8);
+
+        assert_eq!(Color { r: 0, g: 255, b: 0 }.nearest_ansi16(false), 10);
+        assert_eq!(Color { r: 0, g: 200, b: 0 }.nearest_ansi16(false), 2);
+        assert_eq!(Color { r: 250, g: 250, b: 250 }.nearest_ansi16(false), 15);
+    }
+
+    #[test]
+    fn colors_parse() {
+        assert_eq!("red".parse(), Ok(Color { r: 255, g: 0, b: 0 }));
+        assert_eq!("#00ff7F".parse(), Ok(Color { r: 0, g: 255, b: 127 }));
+        assert_eq!("12, 34,56".parse(), Ok(Color { r: 12, g: 34, b: 56 }));
+
+        for s in &["", "grean", "#00ff0", "#00ff00ff", "#gg0000", "#ééé", "1,2", "1,2,3,4", "0,0,256", "-1,0,0"] {
+            assert!(s.parse::<Color>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn color_modes_parse() {
+        assert_eq!("truecolor".parse(), Ok(ColorMode::TrueColor));
+        assert_eq!("24bit".parse(), Ok(ColorMode::TrueColor));
+        assert_eq!("256".parse(), Ok(ColorMode::Ansi256));
+        assert_eq!("16".parse(), Ok(ColorMode::Ansi16));
+        assert_eq!("mono".parse(), Ok(ColorMode::Mono));
+        assert!("8".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn fg_falls_back_to_the_color_mode() {
+        let fg = |mode| {
+            let mut out = vec![];
+            Color { r: 0, g: 255, b: 0 }.write_fg(&mut out, mode).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(fg(ColorMode::TrueColor), "\x1b[38;2;0;255;0m");
+        assert_eq!(fg(ColorMode::Ansi256), "\x1b[38;5;46m");
+        assert_eq!(fg(ColorMode::Ansi16), "\x1b[92m");
+        assert_eq!(fg(ColorMode::Mono), "");
+    }
+}
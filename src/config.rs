@@ -0,0 +1,238 @@
+use std::{
+    error,
+    fmt,
+    time::Duration
+};
+
+use crate::{
+    charset::Charset,
+    Color,
+    ColorMode,
+    gradient::{Easing, Gradient, Space},
+    theme::{self, Body, Theme}
+};
+
+// Defaults for Config parameters.
+const DEFAULT_TRAIL_DENSITY: u32 = 30;
+const DEFAULT_RAIN_CHARSET: &[char] = &[
+    'x', 'A', 'z', 'O',
+    '\u{00D8}', '\u{01C2}', '\u{03A9}', '\u{01E3}', '\u{03FC}',
+    '\u{305B}', '\u{3091}'
+];
+const DEFAULT_FPS: f64 = 30.0;
+const DEFAULT_MAX_LEN: usize = 12;
+const DEFAULT_GAP: u16 = 2;
+const DEFAULT_MAX_SPEED: f32 = 12.0;
+const DEFAULT_MUTATION_RATE: f32 = 0.3;
+const DEFAULT_MESSAGE_HOLD: f32 = 5.0;
+
+pub const MIN_TRAIL_LEN: usize = 3;
+pub const MAX_TRAIL_LEN: usize = 1000;
+pub const MIN_TRAIL_SPEED: f32 = 1.0;
+pub const MAX_TRAIL_SPEED: f32 = 1000.0;
+pub const MAX_LAYERS: usize = 16;
+
+/// Error for a Config with settings out of range.
+#[derive(Debug)]
+pub struct ConfigError(pub(crate) String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for ConfigError {}
+
+/// A depth layer of trails. Its speed, length and brightness are fractions of
+/// max_speed, max_len and the full brightness of the colors.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Layer {
+    pub speed: f32,
+    pub length: f32,
+    pub brightness: f32,
+    /// Colors of the trails in this layer, if not those of the theme.
+    pub gradient: Option<Gradient>
+}
+
+impl Default for Layer {
+    fn default() -> Layer {
+        Layer { speed: 1.0, length: 1.0, brightness: 1.0, gradient: None }
+    }
+}
+
+impl Layer {
+    /// A layer drawn in the colors of the theme.
+    pub fn new(speed: f32, length: f32, brightness: f32) -> Layer {
+        Layer { speed, length, brightness, gradient: None }
+    }
+
+    /// count layers from farthest to nearest, the nearest at full speed,
+    /// length and brightness and the farthest at about a third.
+    pub fn spread(count: usize) -> Vec<Layer> {
+        (1..=count)
+            .map(|i| {
+                let depth = i as f32 / count as f32;
+                Layer {
+                    speed: 0.3 + 0.7 * depth,
+                    length: 0.4 + 0.6 * depth,
+                    brightness: 0.25 + 0.75 * depth,
+                    gradient: None
+                }
+            })
+            .collect()
+    }
+}
+
+/// How the leading character of each trail is drawn.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct HeadStyle {
+    /// None if the head is drawn as part of the body gradient.
+    pub color: Option<Color>,
+    pub bold: bool,
+    /// Whether the cell after the head is lit halfway between the head and body.
+    pub glow: bool
+}
+
+impl Default for HeadStyle {
+    /// A bold head in the color of the body.
+    fn default() -> HeadStyle {
+        HeadStyle { color: None, bold: true, glow: false }
+    }
+}
+
+/// User-controllable parameters that change rendering.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// Will render 1 trail per $TRAIL_DENSITY terminal squares.
+    pub trail_density: u32,
+    /// Set of characters to sample from when displaying the rain.
+    pub rain_charset: Charset,
+    /// Time between drawn frames. The simulation speed doesn't depend on it.
+    pub frame_time: Duration,
+    /// Colors of each trail, from its bottom to its top. With rainbow set, only
+    /// how it interpolates is used.
+    pub gradient: Gradient,
+    /// Whether each column gets its own hue instead of the gradient's colors.
+    pub rainbow: bool,
+    /// Styling of the bottom character of each trail.
+    pub head: HeadStyle,
+    /// Color of the screen behind the rain, or None for the terminal's own.
+    pub background: Option<Color>,
+    /// How colors are written to the terminal.
+    pub color_mode: ColorMode,
+    /// Trails are between MIN_TRAIL_LEN and max_len characters long.
+    pub max_len: usize,
+    /// Trails in the same column and layer are kept at least this many cells
+    /// apart.
+    pub gap: u16,
+    /// Depth layers from farthest to nearest. Nearer layers are drawn on top.
+    pub layers: Vec<Layer>,
+    /// Trails move between half of max_speed and max_speed cells per second.
+    pub max_speed: f32,
+    /// Average number of times per second that any one glyph changes.
+    pub mutation_rate: f32,
+    /// Seed for all randomness in the simulation, or None to seed from the OS.
+    pub seed: Option<u64>,
+    /// Text that passing trails reveal in the middle of the screen. Lines
+    /// are separated by newlines.
+    pub message: Option<String>,
+    /// Seconds the message is held once revealed, and then left out once it
+    /// has dissolved.
+    pub message_hold: f32
+}
+
+impl Default for Config {
+    /// The built-in defaults, without looking at flags, environment or files.
+    /// Colors are written as 24-bit; ColorMode::detect picks a mode that suits
+    /// the terminal instead.
+    fn default() -> Config {
+        let mut config = Config {
+            trail_density: DEFAULT_TRAIL_DENSITY,
+            rain_charset: Charset::from_chars(DEFAULT_RAIN_CHARSET.to_vec()).unwrap(),
+            frame_time: Duration::from_secs_f64(1.0 / DEFAULT_FPS),
+            gradient: Gradient::new(vec![Color::PURE_GREEN, Color::DARK_GREEN], Space::Rgb, Easing::Linear).unwrap(),
+            rainbow: false,
+            head: HeadStyle::default(),
+            background: None,
+            color_mode: ColorMode::TrueColor,
+            max_len: DEFAULT_MAX_LEN,
+            gap: DEFAULT_GAP,
+            layers: vec![Layer::default()],
+            max_speed: DEFAULT_MAX_SPEED,
+            mutation_rate: DEFAULT_MUTATION_RATE,
+            seed: None,
+            message: None,
+            message_hold: DEFAULT_MESSAGE_HOLD
+        };
+        config.apply_theme(&theme::builtin()[0]).unwrap();
+
+        config
+    }
+}
+
+impl Config {
+    /// Switches the colors of the rain over to theme, or returns an error and
+    /// leaves them as they were if the theme has an empty gradient.
+    pub fn apply_theme(&mut self, theme: &Theme) -> Result<(), ConfigError> {
+        match &theme.body {
+            Body::Gradient(stops) => {
+                self.gradient = self.gradient.with_stops(stops.clone())?;
+                self.rainbow = false;
+            },
+            Body::Rainbow => self.rainbow = true
+        }
+        self.head.color = theme.head;
+        self.background = theme.background;
+
+        Ok(())
+    }
+
+    /// Color for text shown over the rain: the head color, or the brightest
+    /// color of the trails.
+    pub fn text_color(&self) -> Color {
+        match self.head.color {
+            Some(color) => color,
+            None if self.rainbow => Color { r: 255, g: 255, b: 255 },
+            None => self.gradient.at(0.0)
+        }
+    }
+
+    /// Checks that the settings are in range. State::new does this too.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.trail_density == 0 {
+            return Err(ConfigError("trail density must be at least 1".to_string()));
+        }
+        if !(MIN_TRAIL_LEN..=MAX_TRAIL_LEN).contains(&self.max_len) {
+            return Err(ConfigError(format!("max trail length must be between {} and {}", MIN_TRAIL_LEN, MAX_TRAIL_LEN)));
+        }
+        if !(MIN_TRAIL_SPEED..=MAX_TRAIL_SPEED).contains(&self.max_speed) {
+            return Err(ConfigError(format!("max trail speed must be between {} and {}", MIN_TRAIL_SPEED, MAX_TRAIL_SPEED)));
+        }
+        if !(self.mutation_rate >= 0.0 && self.mutation_rate.is_finite()) {
+            return Err(ConfigError("mutation rate must not be negative".to_string()));
+        }
+        if !(self.message_hold >= 0.0 && self.message_hold.is_finite()) {
+            return Err(ConfigError("message hold time must not be negative".to_string()));
+        }
+        if !(1..=MAX_LAYERS).contains(&self.layers.len()) {
+            return Err(ConfigError(format!("there must be between 1 and {} layers", MAX_LAYERS)));
+        }
+        for layer in &self.layers {
+            // Fractions past 1 would get around the trail length and speed
+            // limits.
+            if !(layer.speed > 0.0 && layer.speed <= 1.0 && layer.length > 0.0 && layer.length <= 1.0) {
+                return Err(ConfigError("layer speed and length must be above 0 and at most 1".to_string()));
+            }
+            if !(layer.brightness >= 0.0 && layer.brightness.is_finite()) {
+                return Err(ConfigError("layer brightness must not be negative".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
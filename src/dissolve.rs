@@ -0,0 +1,71 @@
+use rand::Rng;
+
+use matrix::{
+    color::Color,
+    render::{Cell, Frame, TermPos}
+};
+
+// Seconds until the last glyph is gone.
+const DISSOLVE_TIME: f32 = 1.2;
+// Seconds each glyph takes to fade out.
+const FADE_TIME: f32 = 0.3;
+
+// Fades out what was on screen a glyph at a time, in random order, for a
+// gentler way out than clearing the screen.
+#[derive(Debug)]
+pub struct Dissolve {
+    background: Option<Color>,
+    // Each glyph, with the time it starts fading out.
+    glyphs: Vec<(TermPos, Cell, f32)>
+}
+
+impl Dissolve {
+    pub fn new<R: Rng>(frame: &Frame, rng: &mut R) -> Dissolve {
+        Dissolve {
+            background: frame.background,
+            glyphs: frame.glyphs()
+                .map(|(pos, cell)| (pos, cell, rng.gen_range(0.0..DISSOLVE_TIME - FADE_TIME)))
+                .collect()
+        }
+    }
+
+    pub fn is_done(&self, elapsed: f32) -> bool {
+        elapsed >= DISSOLVE_TIME
+    }
+
+    // Draws what is left elapsed seconds in.
+    pub fn render(&self, frame: &mut Frame, elapsed: f32) {
+        frame.background = self.background;
+        for &(pos, cell, start) in &self.glyphs {
+            let left = 1.0 - (elapsed - start).max(0.0) / FADE_TIME;
+            if left > 0.0 {
+                frame.set(pos, Cell::new(cell.ch, cell.fg.scale(left), cell.bold));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn glyphs_fade_out_until_none_are_left() {
+        let mut frame = Frame::new((10, 4));
+        for x in 1..=10 {
+            frame.set(TermPos { x, y: 2 }, Cell::new('x', Color { r: 0, g: 200, b: 0 }, false));
+        }
+        let dissolve = Dissolve::new(&frame, &mut thread_rng());
+
+        let count = |elapsed| {
+            let mut frame = Frame::new((10, 4));
+            dissolve.render(&mut frame, elapsed);
+            frame.glyphs().count()
+        };
+        assert_eq!(count(0.0), 10);
+        assert!(!dissolve.is_done(DISSOLVE_TIME / 2.0));
+        assert!(dissolve.is_done(DISSOLVE_TIME));
+        assert_eq!(count(DISSOLVE_TIME), 0);
+    }
+}
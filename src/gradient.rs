@@ -0,0 +1,294 @@
+use std::str::FromStr;
+
+use crate::{Color, ConfigError};
+
+/// Color space that a gradient is interpolated in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Space {
+    /// Straight linear interpolation of the sRGB channels.
+    Rgb,
+    /// Interpolation in OKLab, which keeps perceived lightness changing evenly.
+    OkLab
+}
+
+impl FromStr for Space {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Space, ()> {
+        match s {
+            "rgb" => Ok(Space::Rgb),
+            "oklab" => Ok(Space::OkLab),
+            _ => Err(())
+        }
+    }
+}
+
+/// Curve applied to the position along a gradient before interpolating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut
+}
+
+impl Easing {
+    // Maps t in [0, 1] onto [0, 1], fixing both ends and never decreasing.
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Easing, ()> {
+        match s {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            _ => Err(())
+        }
+    }
+}
+
+/// A gradient through any number of evenly spaced color stops.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    stops: Vec<Color>,
+    space: Space,
+    easing: Easing
+}
+
+impl Gradient {
+    /// Returns an error if stops is empty.
+    pub fn new(stops: Vec<Color>, space: Space, easing: Easing) -> Result<Gradient, ConfigError> {
+        if stops.is_empty() {
+            return Err(ConfigError("a gradient needs at least one color stop".to_string()));
+        }
+
+        Ok(Gradient { stops, space, easing })
+    }
+
+    /// A gradient through different stops, interpolated the same way as self.
+    pub fn with_stops(&self, stops: Vec<Color>) -> Result<Gradient, ConfigError> {
+        Gradient::new(stops, self.space, self.easing)
+    }
+
+    /// The color at position t, where 0 is the first stop and 1 the last.
+    pub fn at(&self, t: f32) -> Color {
+        let last = self.stops.len() - 1;
+        if last == 0 {
+            return self.stops[0];
+        }
+
+        let t = self.easing.apply(t.clamp(0.0, 1.0)) * last as f32;
+        let i = (t.floor() as usize).min(last - 1);
+        let (c1, c2) = (self.stops[i], self.stops[i + 1]);
+        let u = t - i as f32;
+
+        match self.space {
+            Space::Rgb => mix_rgb(c1, c2, u),
+            Space::OkLab => OkLab::from(c1).mix(OkLab::from(c2), u).into()
+        }
+    }
+
+    /// n colors sampled evenly along the gradient, starting at the first stop
+    /// and ending exactly on the last one.
+    pub fn steps(&self, n: usize) -> Vec<Color> {
+        if n == 1 {
+            return vec![self.at(0.0)];
+        }
+
+        (0..n).map(|i| self.at(i as f32 / (n - 1) as f32)).collect()
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn to_channel(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+pub fn mix_rgb(c1: Color, c2: Color, t: f32) -> Color {
+    Color {
+        r: to_channel(lerp(c1.r as f32, c2.r as f32, t)),
+        g: to_channel(lerp(c1.g as f32, c2.g as f32, t)),
+        b: to_channel(lerp(c1.b as f32, c2.b as f32, t))
+    }
+}
+
+// A color in the OKLab space (https://bottosson.github.io/posts/oklab/).
+#[derive(Debug, Clone, Copy)]
+struct OkLab {
+    l: f32,
+    a: f32,
+    b: f32
+}
+
+impl OkLab {
+    fn mix(self, other: OkLab, t: f32) -> OkLab {
+        OkLab {
+            l: lerp(self.l, other.l, t),
+            a: lerp(self.a, other.a, t),
+            b: lerp(self.b, other.b, t)
+        }
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    to_channel(c * 255.0)
+}
+
+impl From<Color> for OkLab {
+    fn from(c: Color) -> OkLab {
+        let (r, g, b) = (srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b));
+
+        let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+        OkLab {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s
+        }
+    }
+}
+
+impl From<OkLab> for Color {
+    fn from(c: OkLab) -> Color {
+        let l = (c.l + 0.396_337_78 * c.a + 0.215_803_76 * c.b).powi(3);
+        let m = (c.l - 0.105_561_346 * c.a - 0.063_854_17 * c.b).powi(3);
+        let s = (c.l - 0.089_484_18 * c.a - 1.291_485_5 * c.b).powi(3);
+
+        Color {
+            r: linear_to_srgb(4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s),
+            g: linear_to_srgb(-1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s),
+            b: linear_to_srgb(-0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACES: [Space; 2] = [Space::Rgb, Space::OkLab];
+    const EASINGS: [Easing; 4] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut];
+
+    fn green_fade(space: Space, easing: Easing) -> Gradient {
+        Gradient::new(vec![Color::PURE_GREEN, Color::DARK_GREEN], space, easing).unwrap()
+    }
+
+    #[test]
+    fn steps_land_on_both_endpoints() {
+        for &space in &SPACES {
+            for &easing in &EASINGS {
+                for len in 2..=40 {
+                    let colors = green_fade(space, easing).steps(len);
+                    assert_eq!(colors.len(), len);
+                    assert_eq!(colors[0], Color::PURE_GREEN);
+                    assert_eq!(colors[len - 1], Color::DARK_GREEN);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_gradient_is_rejected() {
+        assert!(Gradient::new(vec![], Space::Rgb, Easing::Linear).is_err());
+        assert!(green_fade(Space::Rgb, Easing::Linear).with_stops(vec![]).is_err());
+    }
+
+    #[test]
+    fn single_step_is_start_color() {
+        assert_eq!(green_fade(Space::Rgb, Easing::Linear).steps(1), vec![Color::PURE_GREEN]);
+    }
+
+    #[test]
+    fn fade_is_monotonic() {
+        for &space in &SPACES {
+            for &easing in &EASINGS {
+                let colors = green_fade(space, easing).steps(12);
+                for pair in colors.windows(2) {
+                    assert!(pair[1].g <= pair[0].g, "{:?} brightens in {:?} {:?}", pair, space, easing);
+                    assert_eq!(pair[1].r, 0);
+                    assert_eq!(pair[1].b, 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn twelve_steps_are_distinct() {
+        let colors = green_fade(Space::Rgb, Easing::Linear).steps(12);
+        for pair in colors.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn extreme_endpoints_do_not_wrap() {
+        let black = Color { r: 0, g: 0, b: 0 };
+        let white = Color { r: 255, g: 255, b: 255 };
+        for &space in &SPACES {
+            let colors = Gradient::new(vec![white, black], space, Easing::Linear).unwrap().steps(7);
+            assert_eq!(colors[0], white);
+            assert_eq!(colors[6], black);
+            for pair in colors.windows(2) {
+                assert!(pair[1].r <= pair[0].r);
+            }
+        }
+    }
+
+    #[test]
+    fn multi_stop_passes_through_middle_stop() {
+        let red = Color { r: 255, g: 0, b: 0 };
+        let blue = Color { r: 0, g: 0, b: 255 };
+        for &space in &SPACES {
+            let gradient = Gradient::new(vec![red, Color::PURE_GREEN, blue], space, Easing::Linear).unwrap();
+            assert_eq!(gradient.at(0.0), red);
+            assert_eq!(gradient.at(0.5), Color::PURE_GREEN);
+            assert_eq!(gradient.at(1.0), blue);
+        }
+    }
+
+    #[test]
+    fn out_of_range_positions_are_clamped() {
+        let gradient = green_fade(Space::Rgb, Easing::Linear);
+        assert_eq!(gradient.at(-1.0), Color::PURE_GREEN);
+        assert_eq!(gradient.at(2.0), Color::DARK_GREEN);
+    }
+
+    #[test]
+    fn easing_fixes_endpoints() {
+        for &easing in &EASINGS {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn oklab_round_trips() {
+        for &c in &[Color::PURE_GREEN, Color::DARK_GREEN, Color { r: 12, g: 200, b: 99 }] {
+            assert_eq!(Color::from(OkLab::from(c)), c);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use matrix::{
+    color::Color,
+    render::{display_width, Cell, Frame, TermPos}
+};
+
+// Seconds to type each character.
+const TYPE_TIME: f32 = 0.08;
+// Seconds a line stays up once typed, unless the script says otherwise.
+const DEFAULT_HOLD: f32 = 2.0;
+// Seconds the cursor is shown and then hidden for while it blinks.
+const BLINK_TIME: f32 = 0.5;
+const CURSOR: char = '\u{2588}';
+// Where lines are typed, leaving a margin around the corner of the screen.
+const TEXT_POS: TermPos = TermPos { x: 2, y: 2 };
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntroLine {
+    pub text: String,
+    // Seconds the line is held after it has been typed out.
+    pub hold: f32
+}
+
+// Lines typed out one after another, each on a blank screen, before the rain
+// starts. The cursor stays solid while typing and blinks in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intro {
+    pub lines: Vec<IntroLine>
+}
+
+impl Intro {
+    // Seconds from the first character typed until the last line is cleared.
+    pub fn duration(&self) -> f32 {
+        self.lines.iter().map(IntroLine::duration).sum()
+    }
+
+    // Draws the intro as it is elapsed seconds in. Draws nothing once it's
+    // over.
+    pub fn render(&self, frame: &mut Frame, elapsed: f32, color: Color) {
+        let mut start = 0.0;
+        for line in &self.lines {
+            let t = elapsed - start;
+            start += line.duration();
+            if t >= line.duration() {
+                continue;
+            }
+
+            let typed = ((t / TYPE_TIME) as usize).min(line.text.chars().count());
+            let text: String = line.text.chars().take(typed).collect();
+            frame.put_str(TEXT_POS, &text, color);
+
+            let typing = typed < line.text.chars().count();
+            let blink_on = (t - line.typing_time()) / BLINK_TIME % 2.0 < 1.0;
+            if typing || blink_on {
+                let width: u16 = text.chars().map(display_width).sum();
+                let pos = TermPos { x: TEXT_POS.x.saturating_add(width), ..TEXT_POS };
+                frame.set(pos, Cell::new(CURSOR, color, false));
+            }
+            return;
+        }
+    }
+}
+
+impl IntroLine {
+    fn typing_time(&self) -> f32 {
+        self.text.chars().count() as f32 * TYPE_TIME
+    }
+
+    fn duration(&self) -> f32 {
+        self.typing_time() + self.hold
+    }
+}
+
+// Parses a script of one line of text per line. A line of just @<seconds>
+// sets how long the line before it is held, and lines starting with # and
+// blank lines are skipped. For example:
+//
+//     Wake up, Neo...
+//     @3
+//     The Matrix has you...
+impl FromStr for Intro {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Intro, ()> {
+        let mut lines: Vec<IntroLine> = vec![];
+
+        for line in s.lines() {
+            let line = line.trim_end();
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(hold) = line.strip_prefix('@') {
+                let hold: f32 = hold.trim().parse().map_err(|_| ())?;
+                if !(hold >= 0.0 && hold.is_finite()) {
+                    return Err(());
+                }
+                lines.last_mut().ok_or(())?.hold = hold;
+            } else {
+                lines.push(IntroLine { text: line.to_string(), hold: DEFAULT_HOLD });
+            }
+        }
+
+        if lines.is_empty() {
+            return Err(());
+        }
+
+        Ok(Intro { lines })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: Color = Color { r: 0, g: 255, b: 0 };
+
+    fn row(intro: &Intro, elapsed: f32) -> String {
+        let mut frame = Frame::new((20, 3));
+        intro.render(&mut frame, elapsed, COLOR);
+        (TEXT_POS.x..=20).map(|x| frame.get(x, TEXT_POS.y).map_or(' ', |c| c.ch)).collect::<String>().trim_end().to_string()
+    }
+
+    #[test]
+    fn scripts_parse() {
+        let intro: Intro = "# intro\nWake up, Neo...\n@3\n\nThe Matrix has you...".parse().unwrap();
+        assert_eq!(intro.lines, vec![
+            IntroLine { text: "Wake up, Neo...".to_string(), hold: 3.0 },
+            IntroLine { text: "The Matrix has you...".to_string(), hold: DEFAULT_HOLD }
+        ]);
+    }
+
+    #[test]
+    fn invalid_scripts_are_rejected() {
+        for script in &["", "# nothing", "@2\nhi", "hi\n@soon", "hi\n@-1"] {
+            assert!(script.parse::<Intro>().is_err(), "{:?} parsed", script);
+        }
+    }
+
+    #[test]
+    fn lines_are_typed_out_in_turn() {
+        let intro: Intro = "abc\n@1\nde\n@0.5".parse().unwrap();
+        assert_eq!(row(&intro, 0.0), "\u{2588}");
+        assert_eq!(row(&intro, TYPE_TIME * 2.5), "ab\u{2588}");
+        assert_eq!(row(&intro, TYPE_TIME * 3.0 + 0.1), "abc\u{2588}");
+        // The cursor blinks once the line is typed.
+        assert_eq!(row(&intro, TYPE_TIME * 3.0 + BLINK_TIME + 0.1), "abc");
+        assert_eq!(row(&intro, TYPE_TIME * 3.0 + 1.0 + TYPE_TIME * 1.5), "d\u{2588}");
+        assert_eq!(row(&intro, intro.duration()), "");
+    }
+}
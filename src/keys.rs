@@ -0,0 +1,229 @@
+use std::{collections::HashMap, str::FromStr};
+use termion::event::Key;
+
+// Something that can be done from the keyboard while the rain is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Pause,
+    SpeedUp,
+    SpeedDown,
+    DensityUp,
+    DensityDown,
+    CycleTheme,
+    ToggleHelp
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::Quit,
+        Action::Pause,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::DensityUp,
+        Action::DensityDown,
+        Action::CycleTheme,
+        Action::ToggleHelp
+    ];
+
+    // Name of the action in the [keys] table of the config file.
+    fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Pause => "pause",
+            Action::SpeedUp => "speed-up",
+            Action::SpeedDown => "speed-down",
+            Action::DensityUp => "density-up",
+            Action::DensityDown => "density-down",
+            Action::CycleTheme => "cycle-theme",
+            Action::ToggleHelp => "help"
+        }
+    }
+
+    // Description shown in the help overlay.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Pause => "pause / resume",
+            Action::SpeedUp => "faster",
+            Action::SpeedDown => "slower",
+            Action::DensityUp => "more rain",
+            Action::DensityDown => "less rain",
+            Action::CycleTheme => "next theme",
+            Action::ToggleHelp => "show / hide this help"
+        }
+    }
+
+    fn default_keys(self) -> Vec<Key> {
+        match self {
+            Action::Quit => vec![Key::Char('q'), Key::Esc, Key::Ctrl('c')],
+            Action::Pause => vec![Key::Char(' ')],
+            Action::SpeedUp => vec![Key::Char('+'), Key::Char('=')],
+            Action::SpeedDown => vec![Key::Char('-')],
+            Action::DensityUp => vec![Key::Char(']')],
+            Action::DensityDown => vec![Key::Char('[')],
+            Action::CycleTheme => vec![Key::Char('c')],
+            Action::ToggleHelp => vec![Key::Char('?'), Key::Char('h')]
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Action, ()> {
+        Action::ALL.iter().copied().find(|a| a.name() == s).ok_or(())
+    }
+}
+
+// Parses a key name: a single character, a named key such as "space", "esc",
+// "enter", "tab" or "up", or "ctrl-<char>".
+pub fn parse_key(s: &str) -> Option<Key> {
+    let key = match s {
+        "space" => Key::Char(' '),
+        "esc" => Key::Esc,
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "backspace" => Key::Backspace,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        _ => {
+            let (ctrl, rest) = match s.strip_prefix("ctrl-") {
+                Some(rest) => (true, rest),
+                None => (false, s)
+            };
+
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if ctrl => Key::Ctrl(c.to_ascii_lowercase()),
+                (Some(c), None) => Key::Char(c),
+                _ => return None
+            }
+        }
+    };
+
+    Some(key)
+}
+
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(' ') => "space".to_string(),
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Esc => "esc".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        other => format!("{:?}", other).to_lowercase()
+    }
+}
+
+// Which keys trigger which actions.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: Vec<(Action, Vec<Key>)>
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            keys: Action::ALL.iter().map(|&a| (a, a.default_keys())).collect()
+        }
+    }
+}
+
+impl Bindings {
+    // The default bindings, except that each action in overrides is bound to
+    // exactly the given keys. Those keys are unbound from any other action.
+    pub fn new(overrides: &HashMap<Action, Vec<Key>>) -> Bindings {
+        let mut bindings = Bindings::default();
+
+        for (action, keys) in &mut bindings.keys {
+            match overrides.get(action) {
+                Some(new_keys) => *keys = new_keys.clone(),
+                None => keys.retain(|k| !overrides.values().any(|ks| ks.contains(k)))
+            }
+        }
+
+        bindings
+    }
+
+    // Whether some key is bound to action.
+    pub fn is_bound(&self, action: Action) -> bool {
+        self.keys.iter().any(|(a, keys)| *a == action && !keys.is_empty())
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.keys.iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    // One line per action for the help overlay.
+    pub fn help_lines(&self) -> Vec<String> {
+        self.keys.iter()
+            .filter(|(_, keys)| !keys.is_empty())
+            .map(|(action, keys)| {
+                let names: Vec<String> = keys.iter().map(|&k| key_name(k)).collect();
+                format!("{:<16}{}", names.join(" "), action.description())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(pairs: &[(Action, &[Key])]) -> HashMap<Action, Vec<Key>> {
+        pairs.iter().map(|&(action, keys)| (action, keys.to_vec())).collect()
+    }
+
+    #[test]
+    fn key_names_parse() {
+        assert_eq!(parse_key("q"), Some(Key::Char('q')));
+        assert_eq!(parse_key("space"), Some(Key::Char(' ')));
+        assert_eq!(parse_key("esc"), Some(Key::Esc));
+        assert_eq!(parse_key("up"), Some(Key::Up));
+        assert_eq!(parse_key("ctrl-X"), Some(Key::Ctrl('x')));
+        assert_eq!(parse_key("-"), Some(Key::Char('-')));
+
+        for name in &["", "ctrl-", "ctrl-ab", "qq", "escape"] {
+            assert_eq!(parse_key(name), None, "{:?} parsed", name);
+        }
+    }
+
+    #[test]
+    fn defaults_are_bound() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.action(Key::Char('q')), Some(Action::Quit));
+        assert_eq!(bindings.action(Key::Char(' ')), Some(Action::Pause));
+        assert_eq!(bindings.action(Key::Char('x')), None);
+    }
+
+    #[test]
+    fn overrides_replace_an_actions_keys() {
+        let bindings = Bindings::new(&overrides(&[(Action::Pause, &[Key::Char('p')])]));
+        assert_eq!(bindings.action(Key::Char('p')), Some(Action::Pause));
+        assert_eq!(bindings.action(Key::Char(' ')), None);
+        assert_eq!(bindings.action(Key::Char('q')), Some(Action::Quit));
+    }
+
+    #[test]
+    fn remapped_keys_leave_their_old_action() {
+        let bindings = Bindings::new(&overrides(&[(Action::Quit, &[Key::Char('c')])]));
+        assert_eq!(bindings.action(Key::Char('c')), Some(Action::Quit));
+        assert_eq!(bindings.action(Key::Char('q')), None);
+
+        // Cycling themes has no keys left, so it's left out of the help.
+        let help = bindings.help_lines();
+        assert!(!help.iter().any(|line| line.ends_with(Action::CycleTheme.description())));
+        assert_eq!(help.len(), Action::ALL.len() - 1);
+    }
+}
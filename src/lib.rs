@@ -0,0 +1,47 @@
+//! Matrix digital rain for terminals.
+//!
+//! A State holds the rain for a screen of a given size. Advance it with
+//! State::tick and draw it into a Frame with State::render, either one of your
+//! own or one from a Renderer, which hands it to a Backend to display. For
+//! example, to draw straight to a terminal:
+//!
+//! ```no_run
+//! # use std::{error::Error, io};
+//! # use matrix::{ColorMode, Config, Renderer, State, TerminalBackend, TICK_TIME};
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! let mut state = State::new((80, 24), Config::default())?;
+//! let mut renderer = Renderer::new((80, 24), TerminalBackend::new(io::stdout(), ColorMode::detect()));
+//! loop {
+//!     state.tick(TICK_TIME.as_secs_f32());
+//!     state.render(renderer.begin_frame());
+//!     renderer.present()?;
+//! }
+//! # }
+//! ```
+//!
+//! Public structs with public fields and public enums are `#[non_exhaustive]`,
+//! so they can grow without breaking callers: build them with their new or
+//! default functions and set fields from there. Color and TermPos, which are
+//! only ever their components, are the exception.
+
+pub mod charset;
+pub mod color;
+pub mod config;
+pub mod gradient;
+mod message;
+mod rain;
+pub mod render;
+pub mod theme;
+pub mod timing;
+#[cfg(feature = "ratatui")]
+pub mod widget;
+
+pub use crate::{
+    charset::Charset,
+    color::{Color, ColorMode},
+    config::{Config, ConfigError},
+    rain::State,
+    render::{Backend, Cell, Frame, GridBackend, Renderer, TermPos, TerminalBackend},
+    theme::Theme,
+    timing::TICK_TIME
+};
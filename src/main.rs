@@ -1,289 +1,358 @@
+mod cli;
+mod dissolve;
+mod intro;
+mod keys;
+mod terminal;
+
 use std::{
-    io::{stdout, Stdout, Write, Error, Read},
-    cmp::{min, max},
+    io::{stdout, Stdout, Write, Error},
     thread,
-    time,
-    env
+    time::Instant,
+    env,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering}
+    }
 };
 use termion::{
     terminal_size,
     async_stdin,
-    AsyncReader,
+    event::Event,
+    input::TermRead,
     raw::{IntoRawMode, RawTerminal},
     cursor,
-    color,
-    clear
+    clear,
+    style
 };
-use rand::{
-    thread_rng,
-    Rng
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGWINCH},
+    flag
 };
-use std::str::FromStr;
-
-#[derive(Debug, Clone, Copy)]
-struct Color {
-    r: u8,
-    g: u8,
-    b: u8 
-}
-
-impl Color {
-    const PURE_GREEN: Color = Color { r: 0, g: 5, b: 0};
-    const DARK_GREEN: Color = Color { r: 0, g: 1, b: 0 };
-}
-
-// TermPos is a 1-indexed character cell in the Term.
-#[derive(Debug, Clone, Copy)]
-struct TermPos {
-    x: u8,
-    y: u8
-}
-
-// A Trail is a vertical sequence of characters on the screen.
-#[derive(Debug)]
-struct Trail {
-    // Trails are drawn from the bottom up for its len.
-    // Generally, it should dim in color as its drawn up. 
-    bottom: TermPos,
-    len: usize,
-    speed: i32
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use matrix::{
+    timing::Scheduler,
+    Backend,
+    Color,
+    Frame,
+    Renderer,
+    State,
+    TermPos,
+    TerminalBackend,
+    Theme,
+    TICK_TIME
+};
+use cli::{Args, Settings, USAGE};
+use dissolve::Dissolve;
+use intro::Intro;
+use keys::{Action, Bindings};
+use terminal::TerminalGuard;
+
+// The rain along with how it's being shown, which the keyboard controls.
+struct App {
+    state: State,
+    // Themes that c cycles through, and the one showing.
+    themes: Vec<Theme>,
+    theme_index: usize,
+    keys: Bindings,
+    // Whether the simulation is stopped.
+    paused: bool,
+    // Multiplier on the speed of the simulation.
+    speed_factor: f32,
+    // Whether the keyboard help overlay is shown.
+    show_help: bool
 }
 
-impl Trail {
-    const MAX_LEN: usize = 12;
-    const MAX_SPEED: i32 = 3;
-
-    fn new(x: u8, y: u8, len: usize, speed: i32) -> Trail {
-        Trail {
-            bottom: TermPos {
-                x,
-                y
-            },
-            speed,
-            len
+impl App {
+    fn new(state: State, themes: Vec<Theme>, theme_index: usize, keys: Bindings) -> App {
+        App {
+            state,
+            themes,
+            theme_index,
+            keys,
+            paused: false,
+            speed_factor: 1.0,
+            show_help: false
         }
     }
+}
 
-    fn random(term_size: (u16, u16)) -> Trail {
-        let x = thread_rng().gen_range(1..term_size.0);
-        let y = thread_rng().gen_range(1..term_size.1);
-        let len = thread_rng().gen_range(3..Trail::MAX_LEN);
-        let speed = thread_rng().gen_range(1..Trail::MAX_SPEED);
-
-        Trail::new(x as u8, y as u8, len, speed)
+const MIN_SPEED_FACTOR: f32 = 0.25;
+const MAX_SPEED_FACTOR: f32 = 4.0;
+
+// Carries out a keyboard action. Returns false if the program should quit.
+fn apply(app: &mut App, action: Action) -> bool {
+    match action {
+        Action::Quit => return false,
+        Action::Pause => app.paused = !app.paused,
+        Action::SpeedUp => app.speed_factor = (app.speed_factor * 1.25).min(MAX_SPEED_FACTOR),
+        Action::SpeedDown => app.speed_factor = (app.speed_factor / 1.25).max(MIN_SPEED_FACTOR),
+        Action::DensityUp | Action::DensityDown => {
+            // trail_density is the number of squares per trail, so more rain
+            // means a smaller number.
+            // Worked out in u64 so large densities don't overflow.
+            let density = app.state.config().trail_density as u64;
+            let density = match action {
+                Action::DensityUp => (density * 4 / 5).max(1),
+                _ => density * 5 / 4 + 1
+            };
+            // It's never 0, so this can't fail.
+            app.state.set_density(density.min(u32::MAX as u64) as u32).unwrap();
+        },
+        Action::CycleTheme => {
+            app.theme_index = (app.theme_index + 1) % app.themes.len();
+            // Every theme's gradient was checked to have colors when the
+            // settings were loaded.
+            app.state.set_theme(&app.themes[app.theme_index]).unwrap();
+        },
+        Action::ToggleHelp => app.show_help = !app.show_help
     }
 
-    fn is_visible(&self, term_size: (u16, u16)) -> bool {
-        let top = self.bottom.y as i32 - self.len as i32;
-        top < term_size.1 as i32
-    }
+    true
+}
 
-    fn gen_char(charset: &Vec<char>) -> char {
-        charset[thread_rng().gen_range(0..charset.len())]
+fn render<B: Backend>(renderer: &mut Renderer<B>, app: &App) -> Result<(), Error> {
+    let frame = renderer.begin_frame();
+    app.state.render(frame);
+    if app.show_help {
+        render_help(frame, app);
     }
 
-    fn render(&self, stdout: &mut RawTerminal<Stdout>, rain_charset: &Vec<char>) -> Result<(), Error> {
-        let interpolates: Vec<Color> = interpolate(Color::PURE_GREEN, Color::DARK_GREEN, self.len as u8);
-
-        for i in 0..self.len {
-            let y = (self.bottom.y as i32) - (i as i32);
-            let x = self.bottom.x; 
-            let color: Color = interpolates[i];
-
-            if y < 1 {
-                continue;
-            }
-
-            write!(
-                stdout,
-                "{}{}{}",
-                cursor::Goto(x as u16, y as u16),
-                color::Fg(color::AnsiValue::rgb(color.r, color.g, color.b)),
-                Trail::gen_char(rain_charset)
-            )?;
-            stdout.flush()?;
-        }
-
-        Ok(())
-    }
+    renderer.present()
 }
 
-// Defaults for Config parameters.
-const DEFAULT_TRAIL_DENSITY: u32 = 30;
-const DEFAULT_RAIN_CHARSET: &'static [char] = &[
-    'x', 'A', 'z', 'O',
-    '\u{00D8}', '\u{01C2}', '\u{03A9}', '\u{01E3}', '\u{03FC}',
-    '\u{305B}', '\u{3091}'
-];
-
-
-// User-controllable parameters that change rendering.
-struct Config {
-    // Will render 1 trail per $TRAIL_DENSITY terminal squares.
-    trail_density: u32,
-    // Set of characters to sample from when displaying the rain.
-    rain_charset: Vec<char>
+const HELP_COLOR: Color = Color { r: 255, g: 255, b: 255 };
+
+// Draws the list of key bindings in a box in the middle of the screen.
+fn render_help(frame: &mut Frame, app: &App) {
+    let state = &app.state;
+    let lines = app.keys.help_lines();
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 4;
+    let height = lines.len() + 2;
+    let left = (state.size().0 as usize).saturating_sub(width) / 2 + 1;
+    let top = (state.size().1 as usize).saturating_sub(height) / 2 + 1;
+
+    let border = format!("+{}+", "-".repeat(width - 2));
+    frame.put_str(TermPos { x: left as u16, y: top as u16 }, &border, HELP_COLOR);
+    for (i, line) in lines.iter().enumerate() {
+        let row = format!("| {:<w$} |", line, w = width - 4);
+        frame.put_str(TermPos { x: left as u16, y: (top + 1 + i) as u16 }, &row, HELP_COLOR);
+    }
+    frame.put_str(TermPos { x: left as u16, y: (top + height - 1) as u16 }, &border, HELP_COLOR);
 }
 
-impl Config {
-    pub fn create() -> Config {
-        let trail_density_env: Option<u32> = env::var("TRAIL_DENSITY").ok()
-            .and_then(|s| u32::from_str(&s).ok());
-
-        let trail_density: u32 = match trail_density_env {
-            Some(d) => d,
-            _ => DEFAULT_TRAIL_DENSITY
-        };
-
-        let rain_charset_env: Option<Vec<char>> = env::var("RAIN_CHARSET").ok()
-            .and_then(|s| Some(s.chars().collect()));
-
-        let rain_charset: Vec<char> = match rain_charset_env {
-            Some(cs) => cs,
-            _ => DEFAULT_RAIN_CHARSET.iter().map(|c| *c).collect()
-        };
-
-        Config {
-            trail_density: trail_density,
-            rain_charset: rain_charset
+// Types out the intro before the rain, in the same terminal session. Any key
+// skips it, or in screensaver mode quits. Returns false if the program should
+// quit instead of going on to the rain.
+fn play_intro<B: Backend>(
+    renderer: &mut Renderer<B>,
+    app: &mut App,
+    intro: &Intro,
+    events: &mut impl Iterator<Item = Result<Event, Error>>,
+    screensaver: bool,
+    resized: &AtomicBool,
+    terminated: &AtomicBool
+) -> Result<bool, Error> {
+    let start = Instant::now();
+    loop {
+        let frame_start = Instant::now();
+        let elapsed = start.elapsed().as_secs_f32();
+        if elapsed >= intro.duration() {
+            return Ok(true);
         }
-    }
-}
 
-// Holds all relevant state for rendering the digital rain.
-struct State {
-    // Current trails that are rendered on the terminal.
-    trails: Vec<Trail>,
-    // Dimensions (in characters) of the terminal.
-    term_size: (u16, u16),
-    // Other params used when rendering.
-    config: Config
-}
+        if resized.swap(false, Ordering::Relaxed) {
+            resize(renderer, app);
+        }
 
-impl State {
-    fn new(term_size: (u16, u16)) -> State {
-        let config =  Config::create();
-        let num_trails = (term_size.0 as i32 * term_size.1 as i32) as i32 / config.trail_density as i32;
+        let config = app.state.config();
+        let frame = renderer.begin_frame();
+        frame.background = config.background;
+        intro.render(frame, elapsed, config.text_color());
+        renderer.present()?;
 
-        let mut trails: Vec<Trail> = vec![];
-        for _i in 0..num_trails {
-            trails.push(Trail::random(term_size));
+        if terminated.load(Ordering::Relaxed) {
+            return Ok(false);
         }
-
-        State {
-            trails,
-            term_size,
-            config
+        if events.next().is_some() {
+            // Drop the rest of a key sequence so it isn't taken as a command.
+            while events.next().is_some() {}
+            return Ok(!screensaver);
         }
-    }
-}
 
-fn compute_step_size(c1: u8, c2: u8, steps: u8) -> i32 {
-    if c1 == c2 {
-        return 0;
+        thread::sleep(app.state.config().frame_time.saturating_sub(frame_start.elapsed()));
     }
-
-    let f1 = c1 as f32;
-    let f2 = c2 as f32;
-    let fsteps = steps as f32;
-
-    let step = ((f2 - f1) / fsteps).floor() as i32;
-
-    if step == 0 { 1 } else { step }
-}
-
-const ANSI_RGB_MAX: u8 = 5;
-const ANSI_RGB_MIN: u8 = 0;
-
-fn clip(val: u8, interpolatee_val: u8) -> u8 {
-    let min_bound = min(interpolatee_val, ANSI_RGB_MIN);
-    let max_bound = max(interpolatee_val, ANSI_RGB_MAX);
-
-    min(max(min_bound, val), max_bound)
 }
 
-fn interpolate(c1: Color, c2: Color, steps: u8) -> Vec<Color> {
-    let mut interpolates : Vec<Color> = vec![];
-    let rdelta = compute_step_size(c1.r, c2.r, steps);
-    let gdelta = compute_step_size(c1.g, c2.g, steps);
-    let bdelta = compute_step_size(c1.b, c2.b, steps);
-
-    for i in 0i32..(steps as i32) {
-        (&mut interpolates).push(Color {
-            r: clip((c1.r as i32 + (i * rdelta)) as u8, c2.r),
-            g: clip((c1.g as i32 + (i * gdelta)) as u8, c2.g),
-            b: clip((c1.b as i32 + (i * bdelta)) as u8, c2.b)
-        });
-    }
-
-    interpolates
-}
+// Fades the rain off the screen on the way out. Any key cuts it short.
+fn play_dissolve<B: Backend>(
+    renderer: &mut Renderer<B>,
+    app: &App,
+    events: &mut impl Iterator<Item = Result<Event, Error>>,
+    terminated: &AtomicBool
+) -> Result<(), Error> {
+    let mut frame = Frame::new(app.state.size());
+    app.state.render(&mut frame);
+    // Seeded like the rain, so --seed replays the way out too.
+    let mut rng = match app.state.config().seed {
+        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+        None => ChaCha8Rng::from_entropy()
+    };
+    let dissolve = Dissolve::new(&frame, &mut rng);
 
-fn tick(state: &mut State) {
-    // Replace trails if they are no longer visible.
-    for i in 0..state.trails.len() {
-        if !state.trails[i].is_visible(state.term_size) {
-            state.trails[i] = Trail::random(state.term_size);
+    let start = Instant::now();
+    loop {
+        let frame_start = Instant::now();
+        let elapsed = start.elapsed().as_secs_f32();
+        if dissolve.is_done(elapsed) || terminated.load(Ordering::Relaxed) || events.next().is_some() {
+            return Ok(());
         }
-    }
 
-    // Move each trail down.
-    for trail in &mut state.trails {
-        trail.bottom.y += trail.speed as u8;
-    }
-}
+        dissolve.render(renderer.begin_frame(), elapsed);
+        renderer.present()?;
 
-fn render(mut stdout: &mut RawTerminal<Stdout>, state: &State) -> Result<(), Error> {
-    write!(stdout, "{}", clear::All)?;
-    for trail in &state.trails {
-        trail.render(&mut stdout, &state.config.rain_charset)?;
+        thread::sleep(app.state.config().frame_time.saturating_sub(frame_start.elapsed()));
     }
-
-    Ok(())
 }
 
-fn read_key(stdin: &mut AsyncReader) -> Option<u8> {
-    match stdin.bytes().next() {
-        Some(event_res) => match event_res {
-             Ok(evt) => Some(evt),
-             Err(_) => None
-        },
-        _ => None
+// Follows the terminal to its new size.
+fn resize<B: Backend>(renderer: &mut Renderer<B>, app: &mut App) {
+    if let Ok(term_size) = terminal_size() {
+        app.state.resize(term_size);
+        renderer.resize(term_size);
     }
 }
 
 fn clear_screen(stdout: &mut RawTerminal<Stdout>) -> Result<(), Error>  {
-    write!(stdout, "{}{}{}", clear::All, cursor::Goto(1,1), color::Fg(color::Reset))?;
+    write!(stdout, "{}{}{}{}", clear::All, cursor::Goto(1,1), termion::color::Fg(termion::color::Reset), style::Reset)?;
     stdout.flush()
 }
 
 fn main() -> Result<(), Error> {
-    // Set up stdin/stdout.
-    let mut stdin = async_stdin();
-    let mut stdout = stdout().into_raw_mode()?;
+    // Parse configuration before touching the terminal.
+    let args = match Args::parse(env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\nFor more information, try '--help'.", e);
+            process::exit(2);
+        }
+    };
+
+    if args.help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("matrix {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    let settings = match Settings::create(&args) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
 
     // Set up data.
-    let term_size: (u16, u16) = match terminal_size() {
-        Ok(size) => size,
-        Err(_) => panic!("cannot get term size")
+    let term_size: (u16, u16) = terminal_size()?;
+    let Settings { config, themes, theme_index, keys, intro, screensaver, duration } = settings;
+    let color_mode = config.color_mode;
+    let state = match State::new(term_size, config) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
     };
+    let mut app = App::new(state, themes, theme_index, keys);
 
-    let mut state: State = State::new(term_size);
+    // Set up stdin/stdout. The guard restores the terminal on every way out,
+    // including errors and panics, so it must come before raw mode.
+    let _guard = TerminalGuard::new();
+    let mut events = async_stdin().events();
+    let mut stdout = stdout().into_raw_mode()?;
+    terminal::enter(&mut stdout)?;
+    if screensaver {
+        terminal::enable_mouse(&mut stdout)?;
+    }
 
-    // Enter main loop.
-    clear_screen(&mut stdout)?;
+    let mut renderer = Renderer::new(term_size, TerminalBackend::new(&mut stdout, color_mode));
+
+    // SIGWINCH only sets this flag; the resize is handled in the main loop.
+    let resized = Arc::new(AtomicBool::new(false));
+    flag::register(SIGWINCH, Arc::clone(&resized))?;
+
+    // Termination signals end the main loop so the terminal is restored on
+    // the way out. A second one while shutting down restores it and exits
+    // immediately.
+    let terminated = Arc::new(AtomicBool::new(false));
+    for &signal in &[SIGTERM, SIGINT, SIGHUP] {
+        terminal::exit_on_repeated(signal, Arc::clone(&terminated))?;
+        flag::register(signal, Arc::clone(&terminated))?;
+    }
+
+    if let Some(intro) = intro {
+        if !play_intro(&mut renderer, &mut app, &intro, &mut events, screensaver, &resized, &terminated)? {
+            clear_screen(&mut stdout)?;
+            return Ok(());
+        }
+    }
+
+    // Enter main loop. The simulation runs in fixed steps, independently of
+    // how often frames are drawn.
+    let mut scheduler = Scheduler::with_frame_time(app.state.config().frame_time);
+    let rain_start = Instant::now();
+    // Whether to fade the rain out on the way out, rather than clear it at
+    // once.
+    let mut dissolve = true;
     loop {
-        tick(&mut state);
-        render(&mut stdout, &state)?;
+        let frame_start = Instant::now();
 
-        match read_key(&mut stdin) {
-            Some(b'q') => break,
-            _ => ()
+        if resized.swap(false, Ordering::Relaxed) {
+            resize(&mut renderer, &mut app);
         }
 
-        thread::sleep(time::Duration::from_millis(150));
+        // Pending ticks are always taken so unpausing doesn't catch up on
+        // the time spent paused.
+        for _i in 0..scheduler.pending_ticks() {
+            if !app.paused {
+                app.state.tick(TICK_TIME.as_secs_f32() * app.speed_factor);
+            }
+        }
+        render(&mut renderer, &app)?;
+
+        let mut running = true;
+        for event in events.by_ref() {
+            if screensaver {
+                running = false;
+                dissolve = false;
+            } else if let Ok(Event::Key(key)) = event {
+                if let Some(action) = app.keys.action(key) {
+                    running = running && apply(&mut app, action);
+                }
+            }
+        }
+        if terminated.load(Ordering::Relaxed) {
+            dissolve = false;
+            break;
+        }
+        if let Some(duration) = duration {
+            running = running && rain_start.elapsed() < duration;
+        }
+        if !running {
+            break;
+        }
+
+        thread::sleep(app.state.config().frame_time.saturating_sub(frame_start.elapsed()));
+    }
+    if dissolve {
+        play_dissolve(&mut renderer, &app, &mut events, &terminated)?;
     }
     clear_screen(&mut stdout)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
@@ -0,0 +1,205 @@
+use rand::Rng;
+
+use crate::{
+    charset::Charset,
+    color::Color,
+    render::{display_width, Cell, Frame, TermPos}
+};
+
+// Seconds a cell flickers through rain glyphs after a trail passes over it,
+// before it settles on its character of the message.
+const SETTLE_TIME: f32 = 0.4;
+// Average seconds a revealed cell stays up once the message starts dissolving.
+const DISSOLVE_TIME: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shown {
+    Hidden,
+    // Showing a random glyph, for the seconds left until it's locked.
+    Settling(char, f32),
+    Locked
+}
+
+// One character of the message at its place on screen.
+#[derive(Debug)]
+struct MessageCell {
+    pos: TermPos,
+    ch: char,
+    shown: Shown
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    // Trails passing over hidden cells lock them in.
+    Revealing,
+    // The whole message is up, for the seconds left.
+    Holding(f32),
+    // Locked cells drop back into the rain one at a time.
+    Dissolving,
+    // Only rain, for the seconds left until the next reveal.
+    Resting(f32)
+}
+
+// A message hidden in the rain, centered on the screen. It's revealed a
+// character at a time as trails pass over it, held, then dissolved again, over
+// and over.
+#[derive(Debug)]
+pub struct Message {
+    cells: Vec<MessageCell>,
+    phase: Phase
+}
+
+impl Message {
+    // Lays text out in the middle of a screen of term_size, one line per line
+    // of text. Anything that doesn't fit is cut off.
+    pub fn new(text: &str, term_size: (u16, u16)) -> Message {
+        let lines: Vec<&str> = text.lines().collect();
+        let top = (term_size.1 as usize).saturating_sub(lines.len()) / 2 + 1;
+
+        let mut cells = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            let y = top + i;
+            if y > term_size.1 as usize {
+                break;
+            }
+
+            let line_width: usize = line.chars().map(|c| display_width(c) as usize).sum();
+            let mut x = (term_size.0 as usize).saturating_sub(line_width) / 2 + 1;
+            for ch in line.chars() {
+                let width = display_width(ch) as usize;
+                if x + width - 1 > term_size.0 as usize {
+                    break;
+                }
+                if width > 0 && ch != ' ' {
+                    cells.push(MessageCell {
+                        pos: TermPos { x: x as u16, y: y as u16 },
+                        ch,
+                        shown: Shown::Hidden
+                    });
+                }
+                x += width;
+            }
+        }
+
+        Message { cells, phase: Phase::Revealing }
+    }
+
+    // Whether the whole message is on screen.
+    pub fn is_revealed(&self) -> bool {
+        self.cells.iter().all(|c| c.shown == Shown::Locked)
+    }
+
+    // Advances the message by dt seconds. covered says whether a trail is
+    // over a position, which starts settling it if it's hidden. hold is how
+    // long the message stays up once revealed, and how long until the next
+    // reveal once it's gone.
+    pub fn tick<R: Rng, F: Fn(TermPos) -> bool>(
+        &mut self,
+        dt: f32,
+        hold: f32,
+        rain_charset: &Charset,
+        covered: F,
+        rng: &mut R
+    ) {
+        match self.phase {
+            Phase::Revealing => {
+                for cell in &mut self.cells {
+                    cell.shown = match cell.shown {
+                        Shown::Hidden if covered(cell.pos) => Shown::Settling(cell.ch, SETTLE_TIME),
+                        Shown::Settling(_, left) if left <= dt => Shown::Locked,
+                        Shown::Settling(_, left) => {
+                            // A glyph of a different width would spill over
+                            // its neighbours.
+                            let glyph = rain_charset.sample(rng);
+                            let glyph = if display_width(glyph) == display_width(cell.ch) { glyph } else { cell.ch };
+                            Shown::Settling(glyph, left - dt)
+                        },
+                        shown => shown
+                    };
+                }
+                if self.is_revealed() {
+                    self.phase = Phase::Holding(hold);
+                }
+            },
+            Phase::Holding(left) if left <= dt => self.phase = Phase::Dissolving,
+            Phase::Holding(left) => self.phase = Phase::Holding(left - dt),
+            Phase::Dissolving => {
+                let chance = (dt / DISSOLVE_TIME).min(1.0) as f64;
+                for cell in &mut self.cells {
+                    if cell.shown == Shown::Locked && rng.gen_bool(chance) {
+                        cell.shown = Shown::Hidden;
+                    }
+                }
+                if self.cells.iter().all(|c| c.shown == Shown::Hidden) {
+                    self.phase = Phase::Resting(hold);
+                }
+            },
+            Phase::Resting(left) if left <= dt => self.phase = Phase::Revealing,
+            Phase::Resting(left) => self.phase = Phase::Resting(left - dt)
+        }
+    }
+
+    // Draws the cells that are showing over whatever is in frame.
+    pub fn render(&self, frame: &mut Frame, color: Color) {
+        for cell in &self.cells {
+            let ch = match cell.shown {
+                Shown::Hidden => continue,
+                Shown::Settling(glyph, _) => glyph,
+                Shown::Locked => cell.ch
+            };
+            frame.set(cell.pos, Cell { ch, fg: color, bold: true });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    fn positions(message: &Message) -> Vec<(char, u16, u16)> {
+        message.cells.iter().map(|c| (c.ch, c.pos.x, c.pos.y)).collect()
+    }
+
+    #[test]
+    fn lines_are_centered_without_spaces() {
+        let message = Message::new("A B\nCD", (10, 5));
+        assert_eq!(positions(&message), vec![('A', 4, 2), ('B', 6, 2), ('C', 5, 3), ('D', 6, 3)]);
+
+        let message = Message::new("ネオ", (6, 1));
+        assert_eq!(positions(&message), vec![('ネ', 2, 1), ('オ', 4, 1)]);
+    }
+
+    #[test]
+    fn text_that_does_not_fit_is_cut_off() {
+        let message = Message::new("ABCDEF\nG\nH", (4, 2));
+        assert_eq!(positions(&message), vec![('A', 1, 1), ('B', 2, 1), ('C', 3, 1), ('D', 4, 1), ('G', 2, 2)]);
+    }
+
+    #[test]
+    fn message_cycles_through_its_phases() {
+        let charset = Charset::from_chars(vec!['x']).unwrap();
+        let mut rng = thread_rng();
+        let mut message = Message::new("HI", (10, 3));
+
+        // Nothing shows until a trail passes over it.
+        message.tick(0.1, 1.0, &charset, |_| false, &mut rng);
+        assert_eq!(message.phase, Phase::Revealing);
+        assert!(message.cells.iter().all(|c| c.shown == Shown::Hidden));
+
+        for _i in 0..10 {
+            message.tick(0.1, 1.0, &charset, |_| true, &mut rng);
+        }
+        assert!(message.is_revealed());
+        assert!(matches!(message.phase, Phase::Holding(_)));
+
+        for _i in 0..200 {
+            message.tick(0.1, 1.0, &charset, |_| false, &mut rng);
+            if message.phase == Phase::Revealing {
+                break;
+            }
+        }
+        assert_eq!(message.phase, Phase::Revealing);
+        assert!(message.cells.iter().all(|c| c.shown == Shown::Hidden));
+    }
+}
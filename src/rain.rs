@@ -0,0 +1,686 @@
+use rand::{
+    Rng,
+    SeedableRng
+};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    charset::Charset,
+    color::Color,
+    config::{Config, ConfigError, MIN_TRAIL_LEN},
+    gradient::mix_rgb,
+    message::Message,
+    render::{Cell, Frame, TermPos},
+    theme::Theme
+};
+
+// A Trail is a vertical sequence of characters on the screen.
+#[derive(Debug)]
+struct Trail {
+    // Column of the trail, 1-indexed like TermPos.
+    x: u16,
+    // Index into config.layers of the depth layer the trail is in.
+    layer: usize,
+    // Row of the bottom of the trail, which it's drawn up from for its len.
+    // Rows above the screen are 0 and below, so trails can slide in from
+    // above the top edge.
+    // Generally, it should dim in color as its drawn up.
+    bottom: i32,
+    len: usize,
+    // In cells per second.
+    speed: f32,
+    // How far the trail has moved past bottom, as a fraction of a cell.
+    offset: f32,
+    // The characters of the trail, from the bottom up. There are always len of
+    // them, and each stays on the same screen cell as the trail moves.
+    glyphs: Vec<char>
+}
+
+impl Trail {
+    fn new<R: Rng>(x: u16, bottom: i32, len: usize, speed: f32, rain_charset: &Charset, rng: &mut R) -> Trail {
+        Trail {
+            x,
+            layer: 0,
+            bottom,
+            speed,
+            offset: 0.0,
+            len,
+            glyphs: (0..len).map(|_| rain_charset.sample(rng)).collect()
+        }
+    }
+
+    // Row of the top of the trail.
+    fn top(&self) -> i32 {
+        self.bottom - self.len as i32 + 1
+    }
+
+    // Moves the trail down by dt seconds worth of its speed. Each time the
+    // trail crosses into a new cell, a new glyph appears under the head and the
+    // oldest drops off the top, so the glyphs already on screen stay put except
+    // for the occasional mutation.
+    fn advance<R: Rng>(&mut self, dt: f32, config: &Config, rng: &mut R) {
+        self.offset += self.speed * dt;
+        let cells = self.offset.floor();
+        self.offset -= cells;
+        self.bottom += cells as i32;
+
+        // Past len cells, every glyph would be new anyway.
+        for _i in 0..(cells as usize).min(self.len) {
+            self.glyphs.insert(0, config.rain_charset.sample(rng));
+        }
+        self.glyphs.truncate(self.len);
+
+        let mutation_chance = (config.mutation_rate * dt).min(1.0) as f64;
+        for glyph in &mut self.glyphs {
+            if rng.gen_bool(mutation_chance) {
+                *glyph = config.rain_charset.sample(rng);
+            }
+        }
+    }
+
+    // Whether the trail is on screen or still to come. Once it has fallen off
+    // the bottom it's gone for good.
+    fn is_visible(&self, term_size: (u16, u16)) -> bool {
+        self.top() <= term_size.1 as i32
+    }
+
+    // The color and boldness of each cell of the trail, from the bottom up.
+    // With a head color, the bottom cell is the head and the body gradient
+    // covers the rest of the trail.
+    // All of it is dimmed to the brightness of the trail's layer.
+    fn styles(&self, config: &Config, term_width: u16) -> Vec<(Color, bool)> {
+        let layer = &config.layers[self.layer];
+        let rainbow_gradient;
+        let gradient = match &layer.gradient {
+            Some(gradient) => gradient,
+            None if config.rainbow => {
+                let hue = (self.x - 1) as f32 / term_width as f32 * 360.0;
+                let color = Color::from_hue(hue);
+                rainbow_gradient = config.gradient.with_stops(vec![color, color.dim()]).unwrap();
+                &rainbow_gradient
+            },
+            None => &config.gradient
+        };
+
+        let mut styles: Vec<(Color, bool)> = match config.head.color {
+            Some(head_color) => {
+                let body = gradient.steps(self.len - 1);
+                let mut styles = vec![(head_color, config.head.bold)];
+                for (i, color) in body.into_iter().enumerate() {
+                    if i == 0 && config.head.glow {
+                        styles.push((mix_rgb(head_color, color, 0.5), false));
+                    } else {
+                        styles.push((color, false));
+                    }
+                }
+                styles
+            },
+            None => gradient.steps(self.len).into_iter().map(|c| (c, false)).collect()
+        };
+
+        if layer.brightness != 1.0 {
+            for (color, _) in &mut styles {
+                *color = color.scale(layer.brightness);
+            }
+        }
+
+        styles
+    }
+
+    // Composes the trail into the frame; cells off screen are dropped.
+    fn render(&self, frame: &mut Frame, config: &Config) {
+        let styles = self.styles(config, frame.width());
+
+        for (i, ((color, bold), ch)) in styles.into_iter().zip(&self.glyphs).enumerate() {
+            let y = self.bottom - i as i32;
+            if y < 1 || y > u16::MAX as i32 {
+                continue;
+            }
+
+            frame.set(
+                TermPos { x: self.x, y: y as u16 },
+                Cell { ch: *ch, fg: color, bold }
+            );
+        }
+    }
+}
+
+/// The digital rain on a screen of a given size. Advance it with tick and draw
+/// it with render.
+pub struct State {
+    // Current trails that are rendered on the terminal.
+    trails: Vec<Trail>,
+    // Dimensions (in characters) of the terminal.
+    term_size: (u16, u16),
+    // Other params used when rendering.
+    config: Config,
+    // The hidden message from config, laid out for term_size.
+    message: Option<Message>,
+    // Source of all randomness in the simulation, so that a seeded run can be
+    // replayed exactly.
+    rng: ChaCha8Rng
+}
+
+impl State {
+    /// Rain for a screen of term_size, or an error if config has settings out
+    /// of range.
+    pub fn new(term_size: (u16, u16), config: Config) -> Result<State, ConfigError> {
+        config.validate()?;
+        let rng = match config.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy()
+        };
+
+        let message = config.message.as_ref().map(|text| Message::new(text, term_size));
+        let mut state = State {
+            trails: vec![],
+            term_size,
+            config,
+            message,
+            rng
+        };
+        // The first trails are spread out over a screen's height above the
+        // top, so they don't all arrive at once.
+        state.fill(term_size.1);
+
+        Ok(state)
+    }
+
+    /// The screen size the rain is laid out for.
+    pub fn size(&self) -> (u16, u16) {
+        self.term_size
+    }
+
+    /// The settings in effect, including changes made while running.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn num_trails(term_size: (u16, u16), config: &Config) -> usize {
+        (term_size.0 as usize * term_size.1 as usize) / config.trail_density as usize
+    }
+
+    // Adds trails until there are as many as the density calls for, or until
+    // there's no room left for more near the top. spread is how much further
+    // up, at most, each one starts than it has to.
+    fn fill(&mut self, spread: u16) {
+        let num_trails = State::num_trails(self.term_size, &self.config);
+        if self.trails.len() >= num_trails {
+            return;
+        }
+
+        // The top and speed of the highest trail in each column of each
+        // layer, indexed by layer * columns + column.
+        let columns = self.columns() as usize;
+        let mut highest: Vec<Option<(i32, f32)>> = vec![None; columns * self.config.layers.len()];
+        for trail in &self.trails {
+            let slot = &mut highest[trail.layer * columns + self.column_of(trail)];
+            if slot.is_none_or(|(top, _)| trail.top() < top) {
+                *slot = Some((trail.top(), trail.speed));
+            }
+        }
+
+        while self.trails.len() < num_trails {
+            match self.spawn(spread, &mut highest) {
+                Some(trail) => self.trails.push(trail),
+                None => break
+            }
+        }
+    }
+
+    // Trails keep to columns as wide as the widest glyph, so the glyphs of
+    // neighboring trails never overlap.
+    fn columns(&self) -> u16 {
+        (self.term_size.0 / self.config.rain_charset.width()).max(1)
+    }
+
+    fn column_of(&self, trail: &Trail) -> usize {
+        ((trail.x - 1) / self.config.rain_charset.width()) as usize
+    }
+
+    // A new trail in a random layer, starting above the top edge. Within a
+    // column of its layer (trails in other layers pass in front or behind),
+    // it starts far enough above the highest trail already there that at
+    // least config.gap empty cells stay between them until that one has left
+    // the screen, even if the new one is faster. It goes in one of the
+    // columns where that lets it start lowest. Returns None if it would have
+    // to start more than a screen height above the top edge in every column,
+    // so that dense rain doesn't pile up off screen and push later trails
+    // ever further up.
+    fn spawn(&mut self, spread: u16, highest: &mut [Option<(i32, f32)>]) -> Option<Trail> {
+        let columns = self.columns() as usize;
+        let layer = self.rng.gen_range(0..self.config.layers.len());
+        let max_len = ((self.config.max_len as f32 * self.config.layers[layer].length).round() as usize).max(MIN_TRAIL_LEN);
+        let len = self.rng.gen_range(MIN_TRAIL_LEN..=max_len);
+        let max_speed = self.config.max_speed * self.config.layers[layer].speed;
+        let speed = self.rng.gen_range(max_speed / 2.0..=max_speed);
+
+        let (term_height, gap) = (self.term_size.1 as i32, self.config.gap as i32);
+        let start = |above: Option<(i32, f32)>| match above {
+            None => 0,
+            Some((top, above_speed)) => {
+                // The trail above moves up to a cell further than its speed
+                // says from its offset, so the gap is kept one cell wider to
+                // be safe.
+                let mut bottom = 0.min(top - 2 - gap) as f64;
+                if speed > above_speed {
+                    let time_on_screen = (term_height + 1 - top) as f64 / above_speed as f64;
+                    bottom -= ((speed - above_speed) as f64 * time_on_screen).ceil();
+                }
+                // Starts more than a screen height up are never used, so a
+                // very slow trail above can't overflow the row.
+                bottom.max(-term_height as f64 - 1.0) as i32
+            }
+        };
+        let slots = &mut highest[layer * columns..(layer + 1) * columns];
+        let starts: Vec<i32> = slots.iter().map(|&above| start(above)).collect();
+        let lowest = *starts.iter().max().unwrap();
+        if lowest < -term_height {
+            return None;
+        }
+        let candidates: Vec<usize> = (0..columns).filter(|&c| starts[c] == lowest).collect();
+        let column = candidates[self.rng.gen_range(0..candidates.len())];
+
+        let bottom = lowest - self.rng.gen_range(0..=spread) as i32;
+        let x = 1 + self.config.rain_charset.width() * column as u16;
+        let trail = Trail {
+            layer,
+            ..Trail::new(x, bottom, len, speed, &self.config.rain_charset, &mut self.rng)
+        };
+        slots[column] = Some((trail.top(), trail.speed));
+
+        Some(trail)
+    }
+
+    /// Adapts the rain to a new terminal size: trails that fell outside the new
+    /// width are dropped, and trails are culled or added to match the density.
+    pub fn resize(&mut self, term_size: (u16, u16)) {
+        self.term_size = term_size;
+
+        let num_trails = State::num_trails(term_size, &self.config);
+        self.trails.truncate(num_trails);
+
+        let column_width = self.config.rain_charset.width();
+        self.trails.retain(|t| t.x + column_width - 1 <= term_size.0);
+
+        // The message starts over in the middle of the new size.
+        self.message = self.config.message.as_ref().map(|text| Message::new(text, term_size));
+
+        self.fill(self.config.gap);
+    }
+
+    /// Changes the number of terminal squares per trail, adding or removing
+    /// trails to match, or returns an error if density is 0.
+    pub fn set_density(&mut self, density: u32) -> Result<(), ConfigError> {
+        if density == 0 {
+            return Err(ConfigError("trail density must be at least 1".to_string()));
+        }
+        self.config.trail_density = density;
+        self.resize(self.term_size);
+
+        Ok(())
+    }
+
+    /// Recolors the running rain, leaving the trails where they are, or
+    /// returns an error if the theme has an empty gradient.
+    pub fn set_theme(&mut self, theme: &Theme) -> Result<(), ConfigError> {
+        self.config.apply_theme(theme)
+    }
+
+    /// Advances the simulation by dt seconds.
+    pub fn tick(&mut self, dt: f32) {
+        // Replace trails once they have fallen off the screen.
+        let term_size = self.term_size;
+        self.trails.retain(|t| t.is_visible(term_size));
+        self.fill(self.config.gap);
+
+        // Move each trail down.
+        for trail in &mut self.trails {
+            trail.advance(dt, &self.config, &mut self.rng);
+        }
+
+        // Trails reveal the cells of the message they pass over.
+        if let Some(message) = &mut self.message {
+            let trails = &self.trails;
+            let column_width = self.config.rain_charset.width();
+            let covered = |pos: TermPos| trails.iter().any(|t| {
+                pos.x >= t.x && pos.x < t.x + column_width
+                    && t.top() <= pos.y as i32 && pos.y as i32 <= t.bottom
+            });
+            message.tick(dt, self.config.message_hold, &self.config.rain_charset, covered, &mut self.rng);
+        }
+    }
+
+    /// Draws the rain into frame, which should be empty and the same size as
+    /// the state. Layers are drawn from the farthest, so nearer ones cover it.
+    pub fn render(&self, frame: &mut Frame) {
+        frame.background = self.config.background;
+        let mut trails: Vec<&Trail> = self.trails.iter().collect();
+        trails.sort_by_key(|t| t.layer);
+        for trail in trails {
+            trail.render(frame, &self.config);
+        }
+        if let Some(message) = &self.message {
+            message.render(frame, self.config.text_color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::Path};
+    use crate::{
+        color::ColorMode,
+        config::{HeadStyle, Layer, MIN_TRAIL_SPEED},
+        render::{Backend, GridBackend, Renderer, TerminalBackend},
+        theme::Body,
+        timing::TICK_TIME
+    };
+
+    #[test]
+    fn trails_start_above_the_top_edge() {
+        let state = State::new((80, 24), Config::default()).unwrap();
+
+        assert!(!state.trails.is_empty());
+        for trail in &state.trails {
+            assert!(trail.bottom <= 0);
+        }
+    }
+
+    #[test]
+    fn very_slow_trails_do_not_overflow_spawning() {
+        assert!(State::new((80, 24), Config { max_speed: 1e-39, ..Config::default() }).is_err());
+
+        let config = Config {
+            trail_density: 4,
+            max_speed: MIN_TRAIL_SPEED,
+            layers: vec![Layer { speed: 1e-39, ..Layer::default() }],
+            seed: Some(5),
+            ..Config::default()
+        };
+        let mut state = State::new((80, 24), config).unwrap();
+        for _i in 0..100 {
+            state.tick(TICK_TIME.as_secs_f32());
+        }
+        assert!(!state.trails.is_empty());
+    }
+
+    #[test]
+    fn resizing_keeps_trails_on_screen_at_the_density() {
+        let config = Config { seed: Some(6), ..Config::default() };
+        let mut state = State::new((80, 24), config).unwrap();
+        let column_width = state.config.rain_charset.width();
+
+        for &term_size in &[(40, 12), (120, 30), (7, 5)] {
+            state.resize(term_size);
+            assert_eq!(state.trails.len(), State::num_trails(term_size, &state.config));
+            for trail in &state.trails {
+                assert!(trail.x >= 1 && trail.x + column_width - 1 <= term_size.0, "trail at column {} on a screen {} wide", trail.x, term_size.0);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_runtime_changes_are_rejected() {
+        let mut state = State::new((40, 12), Config::default()).unwrap();
+        let trails = state.trails.len();
+        assert!(state.set_density(0).is_err());
+        assert_eq!((state.config.trail_density, state.trails.len()), (Config::default().trail_density, trails));
+
+        let empty = Theme { name: "empty".to_string(), head: None, body: Body::Gradient(vec![]), background: None };
+        assert!(state.set_theme(&empty).is_err());
+        assert_eq!(state.config.gradient.at(0.0), Config::default().gradient.at(0.0));
+    }
+
+    #[test]
+    fn trail_crossing_more_cells_than_its_length_keeps_its_length() {
+        let config = Config::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut trail = Trail::new(1, 0, 5, 1e6, &config.rain_charset, &mut rng);
+
+        trail.advance(100.0, &config, &mut rng);
+        assert_eq!(trail.bottom, 100_000_000);
+        assert_eq!(trail.glyphs.len(), 5);
+    }
+
+    #[test]
+    fn trails_reach_past_column_and_row_255() {
+        let term_size = (600, 300);
+        let config = Config { trail_density: 300, max_speed: 400.0, seed: Some(2), ..Config::default() };
+        let mut state = State::new(term_size, config).unwrap();
+
+        let mut lowest = 0;
+        for _i in 0..60 {
+            state.tick(0.05);
+            for trail in &state.trails {
+                assert!(trail.x >= 1 && trail.x <= term_size.0);
+                lowest = lowest.max(trail.bottom);
+            }
+        }
+        assert!(state.trails.iter().any(|t| t.x > 255));
+        assert!(lowest > 255);
+    }
+
+    #[test]
+    fn tick_on_large_terminal_does_not_overflow() {
+        let term_size = (300, 280);
+        let config = Config { trail_density: 300, max_speed: 500.0, ..Config::default() };
+        let mut state = State::new(term_size, config).unwrap();
+
+        for _i in 0..200 {
+            state.tick(0.01);
+            for trail in &state.trails {
+                assert!(trail.x <= term_size.0);
+                assert!(trail.bottom < (term_size.1 as usize + trail.len + 5) as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn trails_in_a_column_keep_their_gap() {
+        for &(gap, max_speed, layers) in &[(0, 12.0, 1), (2, 12.0, 1), (5, 60.0, 1), (2, 12.0, 3)] {
+            let config = Config {
+                trail_density: 4,
+                gap,
+                max_speed,
+                layers: Layer::spread(layers),
+                seed: Some(9),
+                ..Config::default()
+            };
+            let mut state = State::new((20, 30), config).unwrap();
+
+            for _i in 0..400 {
+                state.tick(TICK_TIME.as_secs_f32());
+                for a in &state.trails {
+                    for b in state.trails.iter().filter(|b| b.x == a.x && b.layer == a.layer && b.bottom > a.bottom) {
+                        assert!(b.top() - a.bottom > gap as i32, "trails at {} and {} in column {} are closer than {}", a.bottom, b.bottom, a.x, gap);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn denser_settings_show_more_rain() {
+        let visible_glyphs = |trail_density| {
+            let config = Config { trail_density, seed: Some(4), ..Config::default() };
+            let mut state = State::new((80, 24), config).unwrap();
+            let mut glyphs = 0;
+            for i in 0..600 {
+                state.tick(TICK_TIME.as_secs_f32());
+                if i >= 300 {
+                    glyphs += render_frame(&state).iter().filter(|c| c.is_some()).count();
+                }
+            }
+            glyphs / 300
+        };
+
+        // Past a point trails can't keep their gaps any closer together, but
+        // up to there denser settings fill the screen more.
+        let (sparse, normal, dense) = (visible_glyphs(60), visible_glyphs(30), visible_glyphs(4));
+        assert!(normal > sparse * 3 / 2, "{} glyphs at density 30, {} at density 60", normal, sparse);
+        assert!(dense > normal * 4 / 3, "{} glyphs at density 4, {} at density 30", dense, normal);
+    }
+
+    #[test]
+    fn trails_render_past_column_and_row_255() {
+        let term_size = (400, 300);
+        let config = Config::default();
+        let trail = Trail::new(300, 280, 5, 1.0, &config.rain_charset, &mut ChaCha8Rng::seed_from_u64(0));
+        let mut frame = Frame::new(term_size);
+
+        trail.render(&mut frame, &config);
+
+        for y in 276..=280 {
+            assert_eq!(frame.get(300, y).map(|c| c.ch), Some(trail.glyphs[(280 - y) as usize]));
+        }
+        assert!(frame.get(300, 275).is_none());
+        assert!(frame.get(300 - 256, 280 - 256).is_none());
+    }
+
+    #[test]
+    fn far_layers_are_slower_and_shorter() {
+        let config = Config { layers: Layer::spread(3), seed: Some(4), ..Config::default() };
+        let state = State::new((200, 100), config).unwrap();
+
+        for trail in &state.trails {
+            let layer = &state.config.layers[trail.layer];
+            assert!(trail.speed <= state.config.max_speed * layer.speed);
+            assert!(trail.len <= ((state.config.max_len as f32 * layer.length).round() as usize).max(MIN_TRAIL_LEN));
+        }
+        assert!(state.trails.iter().any(|t| t.layer == 0) && state.trails.iter().any(|t| t.layer == 2));
+    }
+
+    #[test]
+    fn nearer_layers_draw_on_top() {
+        let config = Config { layers: Layer::spread(2), head: HeadStyle { color: None, bold: false, glow: false }, ..Config::default() };
+        let mut state = State::new((10, 10), config).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let near = Trail { layer: 1, ..Trail::new(3, 5, 4, 1.0, &state.config.rain_charset, &mut rng) };
+        let far = Trail { layer: 0, ..Trail::new(3, 6, 4, 1.0, &state.config.rain_charset, &mut rng) };
+        let near_colors: Vec<Color> = near.styles(&state.config, 10).into_iter().map(|(c, _)| c).collect();
+        let far_colors: Vec<Color> = far.styles(&state.config, 10).into_iter().map(|(c, _)| c).collect();
+        assert!(far_colors[0].g < near_colors[0].g);
+        state.trails = vec![near, far];
+
+        let mut frame = Frame::new((10, 10));
+        state.render(&mut frame);
+        for y in 2..=5 {
+            assert_eq!(frame.get(3, y).unwrap().fg, near_colors[(5 - y) as usize]);
+        }
+        assert_eq!(frame.get(3, 6).unwrap().fg, far_colors[0]);
+    }
+
+    fn render_frame(state: &State) -> Vec<Option<Cell>> {
+        let mut frame = Frame::new(state.term_size);
+        state.render(&mut frame);
+
+        (1..=state.term_size.1)
+            .flat_map(|y| (1..=state.term_size.0).map(move |x| (x, y)))
+            .map(|(x, y)| frame.get(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let term_size = (60, 20);
+        let seeded = |seed| State::new(term_size, Config { seed: Some(seed), ..Config::default() }).unwrap();
+        let (mut a, mut b, mut c) = (seeded(7), seeded(7), seeded(8));
+
+        for _i in 0..100 {
+            a.tick(TICK_TIME.as_secs_f32());
+            b.tick(TICK_TIME.as_secs_f32());
+            c.tick(TICK_TIME.as_secs_f32());
+            assert_eq!(render_frame(&a), render_frame(&b));
+        }
+        assert_ne!(render_frame(&a), render_frame(&c));
+    }
+
+    #[test]
+    fn rain_reveals_the_message() {
+        let config = Config {
+            message: Some("WAKE UP".to_string()),
+            head: HeadStyle { color: Some(Color { r: 1, g: 2, b: 3 }), bold: true, glow: false },
+            seed: Some(3),
+            ..Config::default()
+        };
+        let mut state = State::new((40, 12), config).unwrap();
+
+        let mut ticks = 0;
+        while !state.message.as_ref().unwrap().is_revealed() {
+            state.tick(TICK_TIME.as_secs_f32());
+            ticks += 1;
+            assert!(ticks < 10_000, "message was never revealed");
+        }
+
+        let mut frame = Frame::new(state.size());
+        state.render(&mut frame);
+        let row: String = (17..=23).map(|x| frame.get(x, 6).map_or(' ', |c| c.ch)).collect();
+        assert_eq!(row, "WAKE UP");
+        assert_eq!(frame.get(17, 6).unwrap().fg, Color { r: 1, g: 2, b: 3 });
+    }
+
+    // Compares actual against the golden file tests/snapshots/<name>. Run the
+    // tests with UPDATE_SNAPSHOTS=1 to write out the current output instead.
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(name);
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("missing snapshot {}; run with UPDATE_SNAPSHOTS=1 to create it", path.display()));
+        assert!(actual == expected, "{} changed; run with UPDATE_SNAPSHOTS=1 to update it\n{}", name, actual);
+    }
+
+    const SNAPSHOT_SIZE: (u16, u16) = (32, 10);
+    const SNAPSHOT_FRAMES: usize = 6;
+    // Ticks between frames, as if drawing at 4 frames per second, so that
+    // trails have time to come down into view.
+    const TICKS_PER_FRAME: usize = 15;
+
+    // Runs a seeded simulation and draws SNAPSHOT_FRAMES frames to renderer,
+    // calling after_frame after each one.
+    fn run_frames<B: Backend, F: FnMut(usize, &Renderer<B>)>(renderer: &mut Renderer<B>, mut after_frame: F) {
+        let config = Config { seed: Some(1), ..Config::default() };
+        let mut state = State::new(SNAPSHOT_SIZE, config).unwrap();
+
+        for i in 0..SNAPSHOT_FRAMES {
+            for _j in 0..TICKS_PER_FRAME {
+                state.tick(TICK_TIME.as_secs_f32());
+            }
+            state.render(renderer.begin_frame());
+            renderer.present().unwrap();
+            after_frame(i, renderer);
+        }
+    }
+
+    #[test]
+    fn text_frames_match_snapshot() {
+        let mut renderer = Renderer::new(SNAPSHOT_SIZE, GridBackend::new());
+        let mut actual = String::new();
+        run_frames(&mut renderer, |i, renderer| {
+            actual += &format!("-- frame {} --\n{}", i, renderer.backend().text());
+        });
+
+        assert_snapshot("rain.txt", &actual);
+    }
+
+    #[test]
+    fn ansi_frames_match_snapshot() {
+        let mut renderer = Renderer::new(SNAPSHOT_SIZE, TerminalBackend::new(vec![], ColorMode::TrueColor));
+        let mut actual = String::new();
+        let mut written = 0;
+        run_frames(&mut renderer, |i, renderer| {
+            let output = renderer.backend().output();
+            let frame = String::from_utf8(output[written..].to_vec()).unwrap();
+            actual += &format!("-- frame {} --\n{}\n", i, frame.replace('\x1b', "\\e"));
+            written = output.len();
+        });
+
+        assert_snapshot("rain.ansi", &actual);
+    }
+}
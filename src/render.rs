@@ -0,0 +1,418 @@
+use std::io::{Write, Error};
+use termion::{clear, color, cursor, style};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{Color, ColorMode};
+
+/// TermPos is a 1-indexed character cell in the Term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermPos {
+    pub x: u16,
+    pub y: u16
+}
+
+/// A single character cell on the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bold: bool
+}
+
+impl Cell {
+    pub fn new(ch: char, fg: Color, bold: bool) -> Cell {
+        Cell { ch, fg, bold }
+    }
+}
+
+/// Number of terminal columns ch takes up: 1, 2 for East Asian wide and most
+/// emoji, or 0 for control and combining characters, which can't be drawn in a
+/// cell of their own.
+pub fn display_width(ch: char) -> u16 {
+    ch.width().unwrap_or(0) as u16
+}
+
+// What occupies a cell of a Frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Slot {
+    Empty,
+    Glyph(Cell),
+    // The right half of a double-width glyph in the cell to the left.
+    WideTail
+}
+
+/// A Frame is a grid of cells covering the whole terminal.
+#[derive(Clone)]
+pub struct Frame {
+    width: u16,
+    height: u16,
+    cells: Vec<Slot>,
+    /// Color of the screen behind the cells, or None for the terminal's own.
+    pub background: Option<Color>
+}
+
+impl Frame {
+    pub fn new(size: (u16, u16)) -> Frame {
+        Frame {
+            width: size.0,
+            height: size.1,
+            cells: vec![Slot::Empty; size.0 as usize * size.1 as usize],
+            background: None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            *cell = Slot::Empty;
+        }
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x < 1 || y < 1 || x > self.width || y > self.height {
+            return None;
+        }
+
+        Some((y as usize - 1) * self.width as usize + (x as usize - 1))
+    }
+
+    // Empties the cell at index i, along with the other half of the glyph in
+    // it if that is double-width.
+    fn erase(&mut self, i: usize) {
+        match self.cells[i] {
+            Slot::WideTail => self.cells[i - 1] = Slot::Empty,
+            Slot::Glyph(c) if display_width(c.ch) == 2 => self.cells[i + 1] = Slot::Empty,
+            _ => ()
+        }
+        self.cells[i] = Slot::Empty;
+    }
+
+    /// Sets the cell at pos, and the one to its right as well for a
+    /// double-width glyph. Whatever glyphs it overlaps are erased whole, so a
+    /// half of a wide glyph is never left behind. Positions outside of the
+    /// frame, wide glyphs in the last column and zero-width characters are
+    /// ignored.
+    pub fn set(&mut self, pos: TermPos, cell: Cell) {
+        let width = display_width(cell.ch);
+        let i = match self.index(pos.x, pos.y) {
+            Some(i) if width == 1 || (width == 2 && pos.x < self.width) => i,
+            _ => return
+        };
+
+        self.erase(i);
+        if width == 2 {
+            self.erase(i + 1);
+            self.cells[i + 1] = Slot::WideTail;
+        }
+        self.cells[i] = Slot::Glyph(cell);
+    }
+
+    /// Writes s left to right starting at pos.
+    pub fn put_str(&mut self, pos: TermPos, s: &str, fg: Color) {
+        let mut x = pos.x;
+        for ch in s.chars() {
+            self.set(TermPos { x, y: pos.y }, Cell { ch, fg, bold: false });
+            x = x.saturating_add(display_width(ch));
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Every glyph in the frame along with where it starts, row by row.
+    pub fn glyphs(&self) -> impl Iterator<Item = (TermPos, Cell)> + '_ {
+        self.cells.iter().enumerate().filter_map(move |(i, slot)| match slot {
+            Slot::Glyph(c) => {
+                let x = (i % self.width as usize) as u16 + 1;
+                let y = (i / self.width as usize) as u16 + 1;
+                Some((TermPos { x, y }, *c))
+            },
+            _ => None
+        })
+    }
+
+    /// The glyph starting at (x, y), if any.
+    pub fn get(&self, x: u16, y: u16) -> Option<Cell> {
+        match self.index(x, y).map(|i| self.cells[i]) {
+            Some(Slot::Glyph(c)) => Some(c),
+            _ => None
+        }
+    }
+
+    fn slot(&self, x: u16, y: u16) -> Slot {
+        self.index(x, y).map_or(Slot::Empty, |i| self.cells[i])
+    }
+}
+
+/// Something frames can be drawn to.
+pub trait Backend {
+    /// Shows frame in place of the last one. Frames are always the size last
+    /// given to resize.
+    fn draw(&mut self, frame: &Frame) -> Result<(), Error>;
+
+    /// Sets the size of the frames that will be drawn from now on.
+    fn resize(&mut self, size: (u16, u16));
+}
+
+/// Composes frames and hands them to a backend to draw.
+pub struct Renderer<B: Backend> {
+    frame: Frame,
+    backend: B
+}
+
+impl<B: Backend> Renderer<B> {
+    /// A renderer for frames of size, drawing to backend.
+    pub fn new(size: (u16, u16), mut backend: B) -> Renderer<B> {
+        backend.resize(size);
+        Renderer {
+            frame: Frame::new(size),
+            backend
+        }
+    }
+
+    /// Switches to frames of a new size.
+    pub fn resize(&mut self, size: (u16, u16)) {
+        self.frame = Frame::new(size);
+        self.backend.resize(size);
+    }
+
+    /// Returns a cleared frame to compose the next frame into.
+    pub fn begin_frame(&mut self) -> &mut Frame {
+        self.frame.clear();
+        &mut self.frame
+    }
+
+    /// Hands the frame composed since begin_frame to the backend.
+    pub fn present(&mut self) -> Result<(), Error> {
+        self.backend.draw(&self.frame)
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+/// Draws to a terminal with ANSI escape sequences. The previous frame is kept
+/// as what is currently on screen, and each new frame is diffed against it so
+/// only the cells that changed are written out.
+pub struct TerminalBackend<W: Write> {
+    out: W,
+    front: Frame,
+    color_mode: ColorMode,
+    // Whether the screen has to be cleared before the next frame, in which
+    // case the front buffer doesn't reflect what's on screen.
+    needs_clear: bool
+}
+
+impl<W: Write> TerminalBackend<W> {
+    pub fn new(out: W, color_mode: ColorMode) -> TerminalBackend<W> {
+        TerminalBackend {
+            out,
+            front: Frame::new((0, 0)),
+            color_mode,
+            needs_clear: true
+        }
+    }
+
+    /// Everything written so far, e.g. if W is a `Vec<u8>`.
+    pub fn output(&self) -> &W {
+        &self.out
+    }
+}
+
+impl<W: Write> Backend for TerminalBackend<W> {
+    /// Writes the difference between frame and the front buffer to out in a
+    /// single batch.
+    fn draw(&mut self, frame: &Frame) -> Result<(), Error> {
+        let mut buf: Vec<u8> = vec![];
+
+        // Clearing uses the current background color, which then stays set
+        // for every cell written afterwards.
+        if self.needs_clear || frame.background != self.front.background {
+            match frame.background {
+                Some(bg) => bg.write_bg(&mut buf, self.color_mode)?,
+                None => write!(buf, "{}", color::Bg(color::Reset))?
+            }
+            write!(buf, "{}", clear::All)?;
+            self.front.clear();
+            self.needs_clear = false;
+        }
+        // Where the terminal cursor will be after the last write, if known.
+        let mut cursor_pos: Option<(u16, u16)> = None;
+        // The last foreground color written in this batch.
+        let mut fg: Option<Color> = None;
+        // Whether bold is on. Bold is only ever turned on within a batch, so
+        // it always starts out off.
+        let mut bold = false;
+
+        for y in 1..=frame.height {
+            for x in 1..=frame.width {
+                let slot = frame.slot(x, y);
+                // The right half of a wide glyph is drawn along with its left
+                // half, which must have changed too if this did.
+                if slot == self.front.slot(x, y) || slot == Slot::WideTail {
+                    continue;
+                }
+
+                if cursor_pos != Some((x, y)) {
+                    write!(buf, "{}", cursor::Goto(x, y))?;
+                }
+
+                let mut width = 1;
+                match slot {
+                    Slot::Glyph(c) => {
+                        if c.bold != bold {
+                            // SGR 22 (normal intensity) rather than termion's
+                            // NoBold, which is double underline in many terminals.
+                            if c.bold {
+                                write!(buf, "{}", style::Bold)?;
+                            } else {
+                                write!(buf, "{}", style::NoFaint)?;
+                            }
+                            bold = c.bold;
+                        }
+                        if fg != Some(c.fg) {
+                            c.fg.write_fg(&mut buf, self.color_mode)?;
+                            fg = Some(c.fg);
+                        }
+                        write!(buf, "{}", c.ch)?;
+                        width = display_width(c.ch);
+                    },
+                    _ => write!(buf, " ")?
+                }
+
+                cursor_pos = Some((x + width, y));
+            }
+        }
+
+        if bold {
+            write!(buf, "{}", style::NoFaint)?;
+        }
+
+        if !buf.is_empty() {
+            self.out.write_all(&buf)?;
+            self.out.flush()?;
+        }
+
+        self.front.clone_from(frame);
+        Ok(())
+    }
+
+    /// Whatever was on screen is cleared on the next draw.
+    fn resize(&mut self, size: (u16, u16)) {
+        self.front = Frame::new(size);
+        self.needs_clear = true;
+    }
+}
+
+/// Keeps the last frame in memory instead of drawing it anywhere, so what
+/// would be on screen can be inspected.
+pub struct GridBackend {
+    frame: Frame
+}
+
+impl Default for GridBackend {
+    fn default() -> GridBackend {
+        GridBackend::new()
+    }
+}
+
+impl GridBackend {
+    pub fn new() -> GridBackend {
+        GridBackend { frame: Frame::new((0, 0)) }
+    }
+
+    /// The characters of the last frame, one line per row, with empty cells
+    /// as spaces.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for y in 1..=self.frame.height {
+            for x in 1..=self.frame.width {
+                match self.frame.slot(x, y) {
+                    Slot::Glyph(c) => text.push(c.ch),
+                    Slot::Empty => text.push(' '),
+                    Slot::WideTail => ()
+                }
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// The last frame drawn.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+impl Backend for GridBackend {
+    fn draw(&mut self, frame: &Frame) -> Result<(), Error> {
+        self.frame.clone_from(frame);
+        Ok(())
+    }
+
+    fn resize(&mut self, size: (u16, u16)) {
+        self.frame = Frame::new(size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(ch: char) -> Cell {
+        Cell { ch, fg: Color::PURE_GREEN, bold: false }
+    }
+
+    fn row(frame: &Frame, y: u16) -> Vec<Slot> {
+        (1..=frame.width).map(|x| frame.slot(x, y)).collect()
+    }
+
+    #[test]
+    fn wide_glyph_takes_two_cells() {
+        let mut frame = Frame::new((4, 1));
+        frame.set(TermPos { x: 2, y: 1 }, cell('\u{3091}'));
+        assert_eq!(row(&frame, 1), vec![Slot::Empty, Slot::Glyph(cell('\u{3091}')), Slot::WideTail, Slot::Empty]);
+    }
+
+    #[test]
+    fn wide_glyph_does_not_fit_in_last_column() {
+        let mut frame = Frame::new((4, 1));
+        frame.set(TermPos { x: 4, y: 1 }, cell('\u{3091}'));
+        assert_eq!(row(&frame, 1), vec![Slot::Empty; 4]);
+    }
+
+    #[test]
+    fn overlapping_a_wide_glyph_erases_all_of_it() {
+        let mut frame = Frame::new((4, 1));
+        frame.set(TermPos { x: 1, y: 1 }, cell('\u{3091}'));
+        frame.set(TermPos { x: 2, y: 1 }, cell('x'));
+        assert_eq!(row(&frame, 1), vec![Slot::Empty, Slot::Glyph(cell('x')), Slot::Empty, Slot::Empty]);
+
+        frame.set(TermPos { x: 3, y: 1 }, cell('\u{3091}'));
+        frame.set(TermPos { x: 2, y: 1 }, cell('\u{305B}'));
+        assert_eq!(row(&frame, 1), vec![Slot::Empty, Slot::Glyph(cell('\u{305B}')), Slot::WideTail, Slot::Empty]);
+    }
+
+    #[test]
+    fn draw_moves_the_cursor_past_both_cells_of_a_wide_glyph() {
+        let mut renderer = Renderer::new((4, 1), TerminalBackend::new(vec![], ColorMode::Mono));
+        // The first frame clears the screen.
+        renderer.begin_frame();
+        renderer.present().unwrap();
+        let start = renderer.backend().output().len();
+
+        let frame = renderer.begin_frame();
+        frame.set(TermPos { x: 1, y: 1 }, cell('\u{3091}'));
+        frame.set(TermPos { x: 3, y: 1 }, cell('x'));
+        renderer.present().unwrap();
+        let out = String::from_utf8(renderer.backend().output()[start..].to_vec()).unwrap();
+        assert_eq!(out, format!("{}\u{3091}x", cursor::Goto(1, 1)));
+    }
+}
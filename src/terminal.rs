@@ -0,0 +1,119 @@
+use std::{
+    io::{self, Write},
+    mem,
+    panic,
+    sync::{
+        Arc,
+        OnceLock,
+        atomic::{AtomicBool, Ordering}
+    }
+};
+use termion::{cursor, screen};
+
+// Terminal settings from before the program changed anything.
+static ORIGINAL_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+// Reporting of all mouse buttons and movement, in the SGR encoding.
+const MOUSE_ON: &str = "\x1b[?1003h\x1b[?1006h";
+// Default colors and style, a visible cursor, no mouse reporting, and the main
+// screen. Spelled out rather than formatted so a signal handler can write it.
+const RESET: &str = "\x1b[m\x1b[39m\x1b[?25h\x1b[?1006l\x1b[?1003l\x1b[?1049l";
+
+// Switches to the alternate screen, so the user's scrollback is left alone,
+// and hides the cursor.
+pub fn enter<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "{}{}", screen::ToAlternateScreen, cursor::Hide)?;
+    out.flush()
+}
+
+// Has the terminal send mouse events as input, until restore.
+pub fn enable_mouse<W: Write>(out: &mut W) -> io::Result<()> {
+    write!(out, "{}", MOUSE_ON)?;
+    out.flush()
+}
+
+// Puts the terminal back the way the program found it: default colors and
+// style, a visible cursor, no mouse reporting, the main screen with its
+// original contents, and the original termios settings (so raw mode is off).
+// Safe to call more than once.
+pub fn restore() {
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(RESET.as_bytes());
+    let _ = stdout.flush();
+
+    restore_termios();
+}
+
+fn restore_termios() {
+    if let Some(termios) = ORIGINAL_TERMIOS.get() {
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios);
+        }
+    }
+}
+
+// Exits at once when signal arrives while shutting_down is already set, e.g.
+// on a second Ctrl-C while the program is on its way out, after restoring the
+// terminal. The handler only makes async-signal-safe calls: it writes straight
+// to the file descriptor, bypassing the buffer of io::stdout.
+pub fn exit_on_repeated(signal: libc::c_int, shutting_down: Arc<AtomicBool>) -> io::Result<()> {
+    let handler = move || {
+        if shutting_down.load(Ordering::SeqCst) {
+            unsafe {
+                libc::write(libc::STDOUT_FILENO, RESET.as_ptr() as *const libc::c_void, RESET.len());
+            }
+            restore_termios();
+            unsafe {
+                libc::_exit(1);
+            }
+        }
+    };
+    unsafe { signal_hook::low_level::register(signal, handler) }.map(|_| ())
+}
+
+// Restores the terminal when dropped, including while unwinding from a panic.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    // Must be created before the terminal is modified, e.g. put in raw mode.
+    // Also installs a panic hook that restores the terminal before the panic
+    // message is printed, so the message is readable.
+    pub fn new() -> TerminalGuard {
+        let mut termios: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } == 0 {
+            let _ = ORIGINAL_TERMIOS.set(termios);
+        }
+
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+
+        TerminalGuard
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termion::{color, style};
+
+    #[test]
+    fn reset_undoes_everything_the_program_turns_on() {
+        let expected = format!(
+            "{}{}{}\x1b[?1006l\x1b[?1003l{}",
+            style::Reset,
+            color::Fg(color::Reset),
+            cursor::Show,
+            screen::ToMainScreen
+        );
+        assert_eq!(RESET, expected);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::Color;
+
+/// A named set of colors for the rain.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Theme {
+    pub name: String,
+    /// Color of the leading character of each trail, or None to give trails no
+    /// distinct head.
+    pub head: Option<Color>,
+    pub body: Body,
+    /// What Config::background becomes when the theme is applied.
+    pub background: Option<Color>
+}
+
+/// Colors of the body of each trail.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Body {
+    /// Gradient stops, from the head of the trail up.
+    Gradient(Vec<Color>),
+    /// Each column gets its own hue, fading to a dark shade of it.
+    Rainbow
+}
+
+impl Theme {
+    pub fn new(name: &str, head: Option<Color>, body: Body, background: Option<Color>) -> Theme {
+        Theme { name: name.to_string(), head, body, background }
+    }
+}
+
+pub const DEFAULT_THEME: &str = "classic";
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color { r, g, b }
+}
+
+fn theme(name: &str, head: Color, body: Body, background: Option<Color>) -> Theme {
+    Theme::new(name, Some(head), body, background)
+}
+
+/// The themes that are always available, in the order they are cycled through.
+pub fn builtin() -> Vec<Theme> {
+    vec![
+        theme("classic", rgb(224, 255, 224), Body::Gradient(vec![Color::PURE_GREEN, Color::DARK_GREEN]), None),
+        theme("amber", rgb(255, 240, 200), Body::Gradient(vec![rgb(255, 176, 0), rgb(51, 26, 0)]), Some(rgb(13, 8, 0))),
+        theme("tron", rgb(224, 255, 255), Body::Gradient(vec![rgb(0, 180, 255), rgb(0, 26, 51)]), Some(rgb(0, 6, 13))),
+        theme("red-alert", rgb(255, 224, 224), Body::Gradient(vec![rgb(255, 32, 32), rgb(51, 0, 0)]), None),
+        theme("rainbow", rgb(255, 255, 255), Body::Rainbow, None),
+        theme("grayscale", rgb(255, 255, 255), Body::Gradient(vec![rgb(192, 192, 192), rgb(32, 32, 32)]), None)
+    ]
+}
@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+/// Length of one simulation step. The simulation always advances in steps of
+/// this size, however fast or slow frames are being drawn.
+pub const TICK_TIME: Duration = Duration::from_micros(16_667);
+
+// Never try to catch up on more than this much time at once, or two frames
+// if that's longer, e.g. after the process was suspended, so a stall can't
+// snowball into a burst of ticks.
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Fixed-timestep scheduler: accumulates the real time that has passed and
+/// hands it out as whole simulation steps.
+pub struct Scheduler {
+    last: Instant,
+    lag: Duration,
+    max_lag: Duration
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::with_frame_time(Duration::from_secs(0))
+    }
+
+    /// A scheduler polled once every frame_time. Slow frame rates still get
+    /// all the steps they are due, so the rain falls at the same speed.
+    pub fn with_frame_time(frame_time: Duration) -> Scheduler {
+        Scheduler {
+            last: Instant::now(),
+            lag: Duration::from_secs(0),
+            max_lag: MAX_LAG.max(frame_time.saturating_mul(2))
+        }
+    }
+
+    /// Number of simulation steps due since the last call. Leftover time that
+    /// doesn't make up a whole step is carried over to the next call.
+    pub fn pending_ticks(&mut self) -> u32 {
+        self.pending_ticks_at(Instant::now())
+    }
+
+    fn pending_ticks_at(&mut self, now: Instant) -> u32 {
+        self.lag = (self.lag + now.saturating_duration_since(self.last)).min(self.max_lag);
+        self.last = now;
+
+        let mut ticks = 0;
+        while self.lag >= TICK_TIME {
+            self.lag -= TICK_TIME;
+            ticks += 1;
+        }
+
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ticks handed out over the given number of frames, each frame_time apart.
+    fn ticks_over(scheduler: &mut Scheduler, frames: u32, frame_time: Duration) -> u32 {
+        let mut now = scheduler.last;
+        (0..frames)
+            .map(|_| {
+                now += frame_time;
+                scheduler.pending_ticks_at(now)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn slow_frames_get_all_their_ticks() {
+        for &frame_time in &[Duration::from_millis(33), Duration::from_millis(500), Duration::from_secs(1)] {
+            let mut scheduler = Scheduler::with_frame_time(frame_time);
+            let ticks = ticks_over(&mut scheduler, 20, frame_time);
+            let due = (20 * frame_time.as_nanos() / TICK_TIME.as_nanos()) as u32;
+            assert_eq!(ticks, due, "at {:?} per frame", frame_time);
+        }
+    }
+
+    #[test]
+    fn stalls_are_not_caught_up() {
+        let frame_time = Duration::from_millis(500);
+        let mut scheduler = Scheduler::with_frame_time(frame_time);
+        let ticks = ticks_over(&mut scheduler, 1, Duration::from_secs(60));
+        assert_eq!(ticks, (2 * frame_time.as_nanos() / TICK_TIME.as_nanos()) as u32);
+    }
+}
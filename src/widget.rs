@@ -0,0 +1,108 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{self, Modifier, Style},
+    widgets::StatefulWidget
+};
+
+use crate::{Color, ColorMode, Frame, State};
+
+/// A ratatui widget that draws the rain into any area of the screen, e.g. as
+/// the background of a dashboard. The State holds the simulation and is
+/// resized to fit the area whenever it changes; advancing it with State::tick
+/// is up to the caller.
+///
+/// ```no_run
+/// # use std::error::Error;
+/// # use matrix::{widget::Rain, Config, State, TICK_TIME};
+/// # fn draw<B: ratatui::backend::Backend>(terminal: &mut ratatui::Terminal<B>) -> Result<(), Box<dyn Error>> {
+/// let mut rain = State::new((0, 0), Config::default())?;
+/// terminal.draw(|f| f.render_stateful_widget(Rain, f.area(), &mut rain))?;
+/// rain.tick(TICK_TIME.as_secs_f32());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rain;
+
+fn to_ratatui(color: Color, mode: ColorMode) -> Option<style::Color> {
+    match mode {
+        ColorMode::TrueColor => Some(style::Color::Rgb(color.r, color.g, color.b)),
+        ColorMode::Ansi256 => Some(style::Color::Indexed(color.nearest_ansi256())),
+        ColorMode::Ansi16 => Some(style::Color::Indexed(color.nearest_ansi16(false))),
+        ColorMode::Mono => None
+    }
+}
+
+impl StatefulWidget for Rain {
+    type State = State;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut State) {
+        let area = area.intersection(buf.area);
+        let size = (area.width, area.height);
+        if state.size() != size {
+            state.resize(size);
+        }
+        if area.is_empty() {
+            return;
+        }
+
+        let mut frame = Frame::new(size);
+        state.render(&mut frame);
+        let mode = state.config().color_mode;
+
+        // Without a background color, empty cells are left as they are, so
+        // the rain can be drawn over other widgets.
+        let mut base = Style::default();
+        if let Some(bg) = frame.background.and_then(|bg| to_ratatui(bg, mode)) {
+            base = base.bg(bg);
+            buf.set_style(area, base);
+        }
+
+        for (pos, cell) in frame.glyphs() {
+            let mut style = base;
+            if let Some(fg) = to_ratatui(cell.fg, mode) {
+                style = style.fg(fg);
+            }
+            if cell.bold {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+
+            let x = area.x + pos.x - 1;
+            let width = area.right() - x;
+            buf.set_stringn(x, area.y + pos.y - 1, cell.ch.to_string(), width as usize, style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, TICK_TIME};
+
+    #[test]
+    fn rain_stays_inside_its_area() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 40, 20));
+        let area = Rect::new(10, 5, 12, 6);
+        let config = Config { seed: Some(5), trail_density: 4, ..Config::default() };
+        let mut state = State::new((0, 0), config).unwrap();
+
+        for _i in 0..20 {
+            Rain.render(area, &mut buf, &mut state);
+            state.tick(TICK_TIME.as_secs_f32() * 4.0);
+        }
+        Rain.render(area, &mut buf, &mut state);
+
+        assert_eq!(state.size(), (12, 6));
+        let mut drawn = 0;
+        for y in 0..20 {
+            for x in 0..40 {
+                if buf[(x, y)].symbol() != " " {
+                    assert!(area.contains((x, y).into()), "drew outside the area at ({}, {})", x, y);
+                    drawn += 1;
+                }
+            }
+        }
+        assert!(drawn > 0);
+    }
+}
@@ -0,0 +1,53 @@
+// Uses the library the way an embedding program would, through the public API
+// only.
+use matrix::{config::Layer, Config, Frame, GridBackend, Renderer, State, TICK_TIME};
+
+#[test]
+fn rain_renders_into_a_caller_provided_frame() {
+    let mut config = Config::default();
+    config.seed = Some(3);
+    config.trail_density = 10;
+    let mut state = State::new((40, 12), config).unwrap();
+    for _i in 0..30 {
+        state.tick(TICK_TIME.as_secs_f32());
+    }
+
+    let mut frame = Frame::new((40, 12));
+    state.render(&mut frame);
+    let glyphs = (1..=12)
+        .flat_map(|y| (1..=40).map(move |x| (x, y)))
+        .filter(|&(x, y)| frame.get(x, y).is_some())
+        .count();
+    assert!(glyphs > 0);
+}
+
+#[test]
+fn rain_follows_resizes_through_a_renderer() {
+    let mut state = State::new((20, 8), Config::default()).unwrap();
+    let mut renderer = Renderer::new((20, 8), GridBackend::new());
+
+    state.resize((30, 5));
+    renderer.resize((30, 5));
+    state.tick(TICK_TIME.as_secs_f32());
+    state.render(renderer.begin_frame());
+    renderer.present().unwrap();
+
+    let text = renderer.backend().text();
+    assert_eq!(text.lines().count(), 5);
+    assert!(text.lines().all(|line| line.chars().count() <= 30));
+}
+
+#[test]
+fn out_of_range_configs_are_refused() {
+    let mut config = Config::default();
+    config.trail_density = 0;
+    assert!(State::new((20, 8), config).is_err());
+
+    let mut config = Config::default();
+    config.layers = vec![];
+    assert!(State::new((20, 8), config).is_err());
+
+    let mut config = Config::default();
+    config.layers = vec![Layer::new(0.0, 1.0, 1.0)];
+    assert!(State::new((20, 8), config).is_err());
+}